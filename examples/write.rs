@@ -14,7 +14,7 @@ fn main() -> Result<(), EventLoopError> {
         .build(&event_loop)
         .unwrap();
 
-    let mut clipboard =
+    let clipboard =
         unsafe { Clipboard::connect(&window) }.expect("Connect to clipboard");
 
     clipboard
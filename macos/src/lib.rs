@@ -69,7 +69,7 @@ impl Clipboard {
         Ok(unsafe { Id::retain(obj) }.unwrap().to_string())
     }
 
-    pub fn write(&mut self, data: String) -> Result<(), Box<dyn Error>> {
+    pub fn write(&self, data: String) -> Result<(), Box<dyn Error>> {
         let string_array = NSArray::from_vec(vec![ProtocolObject::from_id(
             NSString::from_str(&data),
         )]);
@@ -0,0 +1,21 @@
+/// What a clipboard backend supports.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Whether the primary [`Selection`](crate::Selection) is supported.
+    pub primary: bool,
+    /// Whether the secondary [`Selection`](crate::Selection) is supported.
+    pub secondary: bool,
+    /// The [`format`](crate::format)s that can be read and written.
+    pub formats: Vec<String>,
+    /// Whether formats other than [`formats`](Self::formats) are exchanged
+    /// as they are.
+    pub any_format: bool,
+    /// Whether [`Clipboard::subscribe`](crate::Clipboard::subscribe) reports
+    /// changes.
+    pub change_notifications: bool,
+    /// Whether lazy contents are only produced when requested, instead of
+    /// upfront.
+    pub lazy_data: bool,
+    /// Whether the contents written survive the exit of the process.
+    pub persistent: bool,
+}
@@ -0,0 +1,47 @@
+use std::io;
+
+/// An error produced by a [`Clipboard`](crate::Clipboard) operation.
+///
+/// Every backend maps its own failures onto these variants, so callers can
+/// react to them without knowing which platform they are running on.
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The clipboard has no contents.
+    #[error("the clipboard is empty")]
+    Empty,
+    /// The clipboard owner did not answer in time.
+    #[error("the clipboard owner did not respond in time")]
+    Timeout,
+    /// The read was cancelled through its [`CancelToken`].
+    ///
+    /// [`CancelToken`]: crate::CancelToken
+    #[error("the clipboard read was cancelled")]
+    Cancelled,
+    /// The clipboard contents are larger than the most bytes allowed by the
+    /// [`ReadOptions`].
+    ///
+    /// [`ReadOptions`]: crate::ReadOptions
+    #[error("the clipboard contents are too large")]
+    TooLarge,
+    /// The clipboard contents are not available in the requested format.
+    #[error(
+        "the clipboard contents are not available in the requested format"
+    )]
+    UnsupportedFormat,
+    /// The clipboard contents could not be decoded as text.
+    #[error("the clipboard contents are not valid UTF-8")]
+    InvalidEncoding,
+    /// Another client took the ownership of the clipboard.
+    #[error("the clipboard ownership was lost")]
+    OwnershipLost,
+    /// The operation is not supported by the clipboard backend.
+    #[error("the operation is not supported by the clipboard backend")]
+    Unsupported,
+    /// There is no clipboard backend available for the window.
+    #[error("no clipboard backend is available")]
+    BackendUnavailable,
+    /// The communication with the clipboard backend failed.
+    #[error("clipboard I/O failed: {0}")]
+    Io(#[from] io::Error),
+}
@@ -0,0 +1,303 @@
+use crate::{format, Clipboard, Error};
+
+use std::path::{Path, PathBuf};
+
+/// The format understood by GNOME and most GTK file managers, which carries
+/// the [`Operation`] alongside the file URIs.
+const GNOME_COPIED_FILES: &str = "x-special/gnome-copied-files";
+
+/// The format used by KDE to mark files as cut.
+const KDE_CUT_SELECTION: &str = "application/x-kde-cutselection";
+
+/// What should happen to files once they are pasted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Operation {
+    /// The files are copied.
+    #[default]
+    Copy,
+    /// The files are moved.
+    Cut,
+}
+
+impl Clipboard {
+    /// Reads a list of files from the clipboard, alongside the [`Operation`]
+    /// that should be performed on paste.
+    pub fn read_files(&self) -> Result<(Vec<PathBuf>, Operation), Error> {
+        let available = self.available_formats()?;
+        let is_offered = |name| available.iter().any(|offered| offered == name);
+
+        if is_offered(GNOME_COPIED_FILES) {
+            let data = self.read_format(GNOME_COPIED_FILES)?;
+            let text = std::str::from_utf8(&data)
+                .map_err(|_| Error::InvalidEncoding)?;
+
+            return parse_gnome(text);
+        }
+
+        if !is_offered(format::URI_LIST) {
+            return Err(if available.is_empty() {
+                Error::Empty
+            } else {
+                Error::UnsupportedFormat
+            });
+        }
+
+        let data = self.read_format(format::URI_LIST)?;
+        let uris =
+            std::str::from_utf8(&data).map_err(|_| Error::InvalidEncoding)?;
+
+        let operation = if is_offered(KDE_CUT_SELECTION)
+            && self.read_format(KDE_CUT_SELECTION)?.starts_with(b"1")
+        {
+            Operation::Cut
+        } else {
+            Operation::Copy
+        };
+
+        Ok((parse(uris)?, operation))
+    }
+
+    /// Writes a list of files to the clipboard, alongside the [`Operation`]
+    /// that should be performed on paste.
+    ///
+    /// Relative paths are resolved against the current directory.
+    pub fn write_files(
+        &self,
+        paths: &[PathBuf],
+        operation: Operation,
+    ) -> Result<(), Error> {
+        let paths = paths
+            .iter()
+            .map(std::path::absolute)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let uris: Vec<String> = paths.iter().map(|path| uri(path)).collect();
+
+        let uri_list: String =
+            uris.iter().map(|uri| format!("{uri}\r\n")).collect();
+
+        let mut gnome_copied_files = String::from(match operation {
+            Operation::Copy => "copy",
+            Operation::Cut => "cut",
+        });
+
+        for uri in &uris {
+            gnome_copied_files.push('\n');
+            gnome_copied_files.push_str(uri);
+        }
+
+        let text = paths
+            .iter()
+            .map(|path| path.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let kde_cut_selection = match operation {
+            Operation::Copy => "0",
+            Operation::Cut => "1",
+        };
+
+        self.write_formats(&[
+            (format::URI_LIST, uri_list.as_bytes()),
+            (GNOME_COPIED_FILES, gnome_copied_files.as_bytes()),
+            (KDE_CUT_SELECTION, kde_cut_selection.as_bytes()),
+            (format::TEXT, text.as_bytes()),
+        ])
+    }
+}
+
+/// Returns the `file://` URI of the given absolute path.
+fn uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt;
+
+        path.as_os_str().as_bytes().to_vec()
+    };
+
+    #[cfg(not(unix))]
+    let bytes = {
+        let path = path.to_string_lossy().replace('\\', "/");
+
+        if path.starts_with('/') {
+            path.into_bytes()
+        } else {
+            format!("/{path}").into_bytes()
+        }
+    };
+
+    for byte in bytes {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~'
+            | b'/' => uri.push(char::from(byte)),
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    uri
+}
+
+/// Parses the local files of a `text/uri-list`.
+///
+/// Comments and URIs that do not point to local files are skipped.
+fn parse(uris: &str) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = Vec::new();
+
+    for line in uris.lines() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some(rest) = line.strip_prefix("file://") else {
+            continue;
+        };
+
+        // Skip the host, which is either empty or refers to this machine
+        let Some(start) = rest.find('/') else {
+            continue;
+        };
+
+        paths.push(path(&decode(&rest[start..])?));
+    }
+
+    Ok(paths)
+}
+
+/// Parses the `x-special/gnome-copied-files` format: the operation on the
+/// first line, followed by the file URIs.
+fn parse_gnome(text: &str) -> Result<(Vec<PathBuf>, Operation), Error> {
+    let (operation, uris) = text.split_once('\n').unwrap_or((text, ""));
+
+    let operation = match operation.trim() {
+        "copy" => Operation::Copy,
+        "cut" => Operation::Cut,
+        _ => return Err(Error::InvalidEncoding),
+    };
+
+    Ok((parse(uris)?, operation))
+}
+
+fn decode(encoded: &str) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = match tail.get(..2) {
+                Some(&[high, low]) => [high, low],
+                _ => return Err(Error::InvalidEncoding),
+            };
+
+            // `from_str_radix` would also take a sign, like in `%+1`
+            let digit = |digit: u8| {
+                char::from(digit).to_digit(16).ok_or(Error::InvalidEncoding)
+            };
+
+            bytes.push((digit(hex[0])? * 16 + digit(hex[1])?) as u8);
+
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(unix)]
+fn path(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path(bytes: &[u8]) -> PathBuf {
+    let path = String::from_utf8_lossy(bytes);
+
+    // Drive letters come after the leading slash, like in `/C:/Users`
+    match path.strip_prefix('/') {
+        Some(rest) if rest.as_bytes().get(1) == Some(&b':') => {
+            PathBuf::from(rest)
+        }
+        _ => PathBuf::from(path.as_ref()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn encodes_and_decodes_paths() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let paths = [
+            PathBuf::from("/home/user/My Documents/100%.txt"),
+            PathBuf::from("/tmp/ünïcode"),
+            PathBuf::from(std::ffi::OsStr::from_bytes(b"/tmp/\xFF\xFE")),
+        ];
+
+        for path in paths {
+            let uri = uri(&path);
+
+            assert!(uri.is_ascii());
+            assert_eq!(parse(&uri).unwrap(), vec![path]);
+        }
+
+        assert_eq!(uri(Path::new("/a b/c%d")), "file:///a%20b/c%25d");
+    }
+
+    #[test]
+    fn skips_the_host() {
+        assert_eq!(
+            parse("file://localhost/tmp/a\nfile:///tmp/b").unwrap(),
+            vec![path(b"/tmp/a"), path(b"/tmp/b")]
+        );
+    }
+
+    #[test]
+    fn skips_comments_and_foreign_uris() {
+        let list =
+            "# comment\r\nfile:///tmp/a\r\n\r\nhttps://example.com/b\r\n";
+
+        assert_eq!(parse(list).unwrap(), vec![path(b"/tmp/a")]);
+    }
+
+    #[test]
+    fn parses_gnome_copied_files() {
+        assert_eq!(
+            parse_gnome("cut\nfile:///tmp/a\nfile:///tmp/b").unwrap(),
+            (vec![path(b"/tmp/a"), path(b"/tmp/b")], Operation::Cut)
+        );
+        assert_eq!(parse_gnome("copy").unwrap(), (Vec::new(), Operation::Copy));
+        assert!(matches!(
+            parse_gnome("move\nfile:///tmp/a"),
+            Err(Error::InvalidEncoding)
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_escapes() {
+        for encoded in ["/a%", "/a%2", "/a%+1", "/a%zz", "/a%-1"] {
+            assert!(
+                matches!(decode(encoded), Err(Error::InvalidEncoding)),
+                "{encoded}"
+            );
+        }
+
+        assert_eq!(decode("/a%2f%2F").unwrap(), b"/a//");
+    }
+}
@@ -0,0 +1,33 @@
+//! Well-known clipboard formats.
+//!
+//! Formats are identified by their MIME type, which is what Wayland
+//! compositors and most X11 clients use to name them.
+
+/// UTF-8 plain text.
+pub const TEXT: &str = "text/plain;charset=utf-8";
+
+/// HTML markup.
+pub const HTML: &str = "text/html";
+
+/// A list of URIs, one per line.
+pub const URI_LIST: &str = "text/uri-list";
+
+/// A PNG image.
+pub const PNG: &str = "image/png";
+
+/// A BMP image.
+pub const BMP: &str = "image/bmp";
+
+/// A JPEG image.
+pub const JPEG: &str = "image/jpeg";
+
+/// The format marking contents, like a password, as secret.
+///
+/// Its value is always `secret`. It is offered by
+/// [`Clipboard::write_sensitive`](crate::Clipboard::write_sensitive).
+pub const PASSWORD_MANAGER_HINT: &str = "x-kde-passwordManagerHint";
+
+/// Returns whether the given format is plain UTF-8 text.
+pub(crate) fn is_text(format: &str) -> bool {
+    matches!(format, TEXT | "text/plain" | "UTF8_STRING")
+}
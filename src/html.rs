@@ -0,0 +1,104 @@
+use crate::{format, Clipboard, Error};
+
+impl Clipboard {
+    /// Reads HTML markup from the clipboard.
+    pub fn read_html(&self) -> Result<String, Error> {
+        decode(&self.read_format(format::HTML)?)
+    }
+
+    /// Writes HTML markup to the clipboard, alongside a plain text
+    /// alternative for readers that do not understand HTML.
+    pub fn write_html(&self, html: &str, alt_text: &str) -> Result<(), Error> {
+        self.write_formats(&[
+            (format::HTML, html.as_bytes()),
+            (format::TEXT, alt_text.as_bytes()),
+        ])
+    }
+}
+
+/// Decodes HTML that may be encoded as UTF-8 or UTF-16.
+///
+/// Some browsers offer `text/html` as UTF-16 on X11, with or without a byte
+/// order mark.
+fn decode(data: &[u8]) -> Result<String, Error> {
+    match data {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => utf8(rest),
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        // Markup is mostly ASCII, so UTF-16 without a byte order mark is
+        // given away by its zero bytes, which never show up in UTF-8 HTML
+        [_, 0, ..] => utf16(data, u16::from_le_bytes),
+        [0, _, ..] => utf16(data, u16::from_be_bytes),
+        _ => utf8(data),
+    }
+}
+
+fn utf8(data: &[u8]) -> Result<String, Error> {
+    String::from_utf8(data.to_vec()).map_err(|_| Error::InvalidEncoding)
+}
+
+fn utf16(data: &[u8], unit: fn([u8; 2]) -> u16) -> Result<String, Error> {
+    if !data.len().is_multiple_of(2) {
+        return Err(Error::InvalidEncoding);
+    }
+
+    let units = data.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+
+    char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .map_err(|_| Error::InvalidEncoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn utf16be(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    #[test]
+    fn decodes_utf8() {
+        assert_eq!(decode(b"<b>hi</b>").unwrap(), "<b>hi</b>");
+        assert_eq!(decode(b"\xEF\xBB\xBF<b>\xC3\xA9</b>").unwrap(), "<b>é</b>");
+    }
+
+    #[test]
+    fn decodes_utf16_with_byte_order_mark() {
+        let little = [&[0xFF, 0xFE][..], &utf16le("<p>é</p>")].concat();
+        let big = [&[0xFE, 0xFF][..], &utf16be("<p>é</p>")].concat();
+
+        assert_eq!(decode(&little).unwrap(), "<p>é</p>");
+        assert_eq!(decode(&big).unwrap(), "<p>é</p>");
+    }
+
+    #[test]
+    fn decodes_utf16_without_byte_order_mark() {
+        assert_eq!(decode(&utf16le("<p>hi</p>")).unwrap(), "<p>hi</p>");
+        assert_eq!(decode(&utf16be("<p>hi</p>")).unwrap(), "<p>hi</p>");
+    }
+
+    #[test]
+    fn rejects_odd_utf16() {
+        let mut data = utf16le("<p>");
+        data.push(b'>');
+
+        assert!(matches!(decode(&data), Err(Error::InvalidEncoding)));
+        assert!(matches!(
+            decode(&[0xFF, 0xFE, b'<']),
+            Err(Error::InvalidEncoding)
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        assert!(matches!(
+            decode(b"<p>\xFF</p>"),
+            Err(Error::InvalidEncoding)
+        ));
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{format, Clipboard, Error};
+
+use ::image::{ImageBuffer, ImageFormat, Rgba};
+use std::io::Cursor;
+
+/// An image made of RGBA pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    /// The width of the image, in pixels.
+    pub width: u32,
+    /// The height of the image, in pixels.
+    pub height: u32,
+    /// The pixels of the image, row by row, 4 bytes per pixel.
+    pub rgba: Vec<u8>,
+}
+
+/// The image formats we can decode, by preference.
+const FORMATS: [(&str, ImageFormat); 3] = [
+    (format::PNG, ImageFormat::Png),
+    (format::BMP, ImageFormat::Bmp),
+    (format::JPEG, ImageFormat::Jpeg),
+];
+
+impl Clipboard {
+    /// Reads an image from the clipboard.
+    ///
+    /// PNG images are preferred, then BMP and JPEG ones.
+    pub fn read_image(&self) -> Result<Image, Error> {
+        let available = self.available_formats()?;
+
+        if available.is_empty() {
+            return Err(Error::Empty);
+        }
+
+        let (name, format) = FORMATS
+            .into_iter()
+            .find(|(name, _)| available.iter().any(|offered| offered == name))
+            .ok_or(Error::UnsupportedFormat)?;
+
+        let data = self.read_format(name)?;
+
+        let image = ::image::load_from_memory_with_format(&data, format)
+            .map_err(|_| Error::InvalidEncoding)?
+            .into_rgba8();
+
+        Ok(Image {
+            width: image.width(),
+            height: image.height(),
+            rgba: image.into_raw(),
+        })
+    }
+
+    /// Writes an image to the clipboard, encoded as PNG.
+    ///
+    /// Fails with [`Error::InvalidEncoding`] if the pixels do not match the
+    /// dimensions of the image.
+    pub fn write_image(&self, image: &Image) -> Result<(), Error> {
+        let pixels = ImageBuffer::<Rgba<u8>, &[u8]>::from_raw(
+            image.width,
+            image.height,
+            &image.rgba,
+        )
+        .ok_or(Error::InvalidEncoding)?;
+
+        let mut png = Vec::new();
+
+        pixels
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(|error| Error::Io(std::io::Error::other(error)))?;
+
+        self.write_formats(&[(format::PNG, &png)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryClipboard;
+
+    fn clipboard() -> Clipboard {
+        Clipboard::from_provider(Box::new(MemoryClipboard::new()))
+    }
+
+    #[test]
+    fn round_trips_through_png() {
+        let clipboard = clipboard();
+        let image = Image {
+            width: 2,
+            height: 1,
+            rgba: vec![255, 0, 0, 255, 0, 0, 255, 128],
+        };
+
+        clipboard.write_image(&image).unwrap();
+
+        assert_eq!(clipboard.available_formats().unwrap(), [format::PNG]);
+        assert_eq!(clipboard.read_image().unwrap(), image);
+    }
+
+    #[test]
+    fn refuses_pixels_not_matching_the_dimensions() {
+        let clipboard = clipboard();
+        let image = Image {
+            width: 2,
+            height: 2,
+            rgba: vec![0; 4],
+        };
+
+        assert!(matches!(
+            clipboard.write_image(&image),
+            Err(Error::InvalidEncoding)
+        ));
+        assert!(matches!(clipboard.read_image(), Err(Error::Empty)));
+    }
+}
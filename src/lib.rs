@@ -46,52 +46,780 @@ mod platform;
 #[path = "platform/dummy.rs"]
 mod platform;
 
+pub mod format;
+
+mod capabilities;
+mod error;
+mod files;
+mod html;
+#[cfg(feature = "image")]
+mod image;
+mod memory;
+mod read;
+mod task;
+
+pub use capabilities::Capabilities;
+pub use error::Error;
+pub use files::Operation;
+#[cfg(feature = "image")]
+pub use image::Image;
+pub use memory::{MemoryClipboard, ReadFailure};
+pub use read::{CancelToken, ReadOptions};
+pub use task::Task;
+
 use raw_window_handle::HasDisplayHandle;
-use std::error::Error;
+use std::io::{self, Read, Write};
+#[cfg(unix)]
+use std::os::fd::BorrowedFd;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+/// A function producing clipboard contents in the given [`format`].
+///
+/// It is called only once a reader asks for the format, and it may be called
+/// again for every request. Returning `None` refuses the request.
+pub type Provider = Arc<dyn Fn(&str) -> Option<Vec<u8>> + Send + Sync>;
+
+/// A function opening a new reader over clipboard contents.
+///
+/// It is called for every request, so every reader gets the contents from
+/// the start.
+pub type Source =
+    Arc<dyn Fn() -> io::Result<Box<dyn Read + Send>> + Send + Sync>;
 
+/// A system selection holding clipboard contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Selection {
+    /// The regular clipboard, used by explicit copy and paste.
+    Clipboard,
+    /// The primary selection, set by selecting text and pasted with the
+    /// middle mouse button.
+    Primary,
+    /// The secondary selection, only found on X11 and rarely used.
+    Secondary,
+}
+
+/// A handle to the clipboard of a display.
+///
+/// It can be cloned and shared across threads; every clone uses the same
+/// connection, and a slow read does not hold up other operations.
+#[derive(Clone)]
 pub struct Clipboard {
-    raw: Box<dyn ClipboardProvider>,
+    raw: Arc<dyn ClipboardProvider>,
 }
 
 impl Clipboard {
-    /// Safety: the display handle must be valid for the lifetime of `Clipboard`
+    /// Connect to the clipboard of the display of the given window.
+    ///
+    /// # Safety
+    ///
+    /// The display handle must be valid for the lifetime of `Clipboard`.
     pub unsafe fn connect<W: HasDisplayHandle>(
         window: &W,
-    ) -> Result<Self, Box<dyn Error>> {
+    ) -> Result<Self, Error> {
         let raw = platform::connect(window)?;
 
-        Ok(Clipboard { raw })
+        Ok(Self::from_provider(raw))
+    }
+
+    /// Connect to the clipboard of the display of the given window, without
+    /// spawning any threads to serve it.
+    ///
+    /// On Linux, other clients are only served while
+    /// [`Clipboard::dispatch_pending`] is called; usually, whenever
+    /// [`Clipboard::fd`] becomes readable or [`Clipboard::next_deadline`]
+    /// passes. On X11, the clipboards of every window share a connection,
+    /// which any of them serves. Other platforms need no threads, so this is
+    /// [`Clipboard::connect`].
+    ///
+    /// # Safety
+    ///
+    /// The display handle must be valid for the lifetime of `Clipboard`.
+    pub unsafe fn connect_without_thread<W: HasDisplayHandle>(
+        window: &W,
+    ) -> Result<Self, Error> {
+        let raw = platform::connect_without_thread(window)?;
+
+        Ok(Self::from_provider(raw))
+    }
+
+    /// Connect to the clipboard of the system, without any window.
+    ///
+    /// On Linux, Wayland is picked if `WAYLAND_DISPLAY` is set, and X11 if
+    /// `DISPLAY` is set, which includes XWayland when the compositor cannot
+    /// be reached. Setting `WINDOW_CLIPBOARD_BACKEND` to `wayland` or `x11`
+    /// forces a backend.
+    ///
+    /// Wayland compositors only let clients with keyboard focus use the
+    /// clipboard, so windowless Wayland clipboards are of little use.
+    pub fn connect_from_env() -> Result<Self, Error> {
+        let raw = platform::connect_from_env()?;
+
+        Ok(Self::from_provider(raw))
+    }
+
+    /// Creates a [`Clipboard`] backed by the given provider.
+    ///
+    /// This is mostly useful to test clipboard interactions with a
+    /// [`MemoryClipboard`].
+    pub fn from_provider(raw: Box<dyn ClipboardProvider>) -> Self {
+        Clipboard {
+            raw: Arc::from(raw),
+        }
+    }
+
+    /// Returns what the clipboard backend supports.
+    pub fn capabilities(&self) -> Capabilities {
+        self.raw.capabilities()
+    }
+
+    pub fn read(&self) -> Result<String, Error> {
+        self.read_from(Selection::Clipboard)
+    }
+
+    pub fn write(&self, contents: String) -> Result<(), Error> {
+        self.write_to(Selection::Clipboard, contents)
+    }
+
+    /// Reads the text contents of the given [`Selection`].
+    ///
+    /// Fails with [`Error::Unsupported`] if the backend lacks the selection.
+    pub fn read_from(&self, selection: Selection) -> Result<String, Error> {
+        self.raw.read_from(selection)
+    }
+
+    /// Reads the text contents of the given [`Selection`] with the given
+    /// [`ReadOptions`].
+    pub fn read_with(
+        &self,
+        selection: Selection,
+        options: &ReadOptions,
+    ) -> Result<String, Error> {
+        self.raw.read_with(selection, options)
+    }
+
+    /// Writes the given text to the given [`Selection`].
+    ///
+    /// Fails with [`Error::Unsupported`] if the backend lacks the selection.
+    pub fn write_to(
+        &self,
+        selection: Selection,
+        contents: String,
+    ) -> Result<(), Error> {
+        self.raw.write_to(selection, contents)
+    }
+
+    /// Writes secret text, like a password, to the clipboard, asking
+    /// clipboard managers not to record it in their history.
+    pub fn write_sensitive(&self, contents: String) -> Result<(), Error> {
+        self.write_sensitive_to(Selection::Clipboard, contents)
+    }
+
+    /// Writes secret text to the given [`Selection`], asking clipboard
+    /// managers not to record it in their history.
+    ///
+    /// Fails with [`Error::Unsupported`] if the backend has no way to ask.
+    pub fn write_sensitive_to(
+        &self,
+        selection: Selection,
+        contents: String,
+    ) -> Result<(), Error> {
+        self.raw.write_sensitive_to(selection, contents)
+    }
+
+    /// Writes text to the clipboard, clearing it once `expiry` passes unless
+    /// it was replaced in the meantime.
+    pub fn write_with_expiry(
+        &self,
+        contents: String,
+        expiry: Duration,
+    ) -> Result<(), Error> {
+        self.write_with_expiry_to(Selection::Clipboard, contents, expiry)
+    }
+
+    /// Writes text to the given [`Selection`], clearing it once `expiry`
+    /// passes unless it was replaced in the meantime.
+    ///
+    /// Fails with [`Error::Unsupported`] if the backend cannot clear it.
+    pub fn write_with_expiry_to(
+        &self,
+        selection: Selection,
+        contents: String,
+        expiry: Duration,
+    ) -> Result<(), Error> {
+        self.raw.write_with_expiry_to(selection, contents, expiry)
     }
 
-    pub fn read(&self) -> Result<String, Box<dyn Error>> {
-        self.raw.read()
+    /// Reads the clipboard contents in the given [`format`].
+    pub fn read_format(&self, format: &str) -> Result<Vec<u8>, Error> {
+        self.read_format_from(Selection::Clipboard, format)
     }
 
-    pub fn write(&mut self, contents: String) -> Result<(), Box<dyn Error>> {
-        self.raw.write(contents)
+    /// Reads the contents of the given [`Selection`] in the given [`format`].
+    pub fn read_format_from(
+        &self,
+        selection: Selection,
+        format: &str,
+    ) -> Result<Vec<u8>, Error> {
+        self.raw.read_format_from(selection, format)
+    }
+
+    /// Reads the contents of the given [`Selection`] in the given [`format`]
+    /// with the given [`ReadOptions`].
+    pub fn read_format_with(
+        &self,
+        selection: Selection,
+        format: &str,
+        options: &ReadOptions,
+    ) -> Result<Vec<u8>, Error> {
+        self.raw.read_format_with(selection, format, options)
+    }
+
+    /// Streams the clipboard contents in the given [`format`] into `output`
+    /// as they arrive, returning the number of bytes written.
+    ///
+    /// Fails with [`Error::TooLarge`] past the `max_bytes` of the
+    /// [`ReadOptions`], in which case `output` holds what arrived until then.
+    pub fn read_to(
+        &self,
+        format: &str,
+        output: &mut impl Write,
+        options: &ReadOptions,
+    ) -> Result<usize, Error> {
+        self.read_selection_to(Selection::Clipboard, format, output, options)
+    }
+
+    /// Streams the contents of the given [`Selection`] in the given
+    /// [`format`] into `output` as they arrive, returning the number of bytes
+    /// written.
+    pub fn read_selection_to(
+        &self,
+        selection: Selection,
+        format: &str,
+        output: &mut impl Write,
+        options: &ReadOptions,
+    ) -> Result<usize, Error> {
+        self.raw.read_to(selection, format, output, options)
+    }
+
+    /// Returns the [`format`]s offered by the current clipboard owner.
+    ///
+    /// An empty clipboard offers no formats.
+    pub fn available_formats(&self) -> Result<Vec<String>, Error> {
+        self.available_formats_in(Selection::Clipboard)
+    }
+
+    /// Returns the [`format`]s offered by the current owner of the given
+    /// [`Selection`].
+    pub fn available_formats_in(
+        &self,
+        selection: Selection,
+    ) -> Result<Vec<String>, Error> {
+        self.raw.available_formats_in(selection)
+    }
+
+    /// Writes the given contents to the clipboard, offering each one in its
+    /// [`format`] at once.
+    ///
+    /// Readers pick the representation they understand best.
+    pub fn write_formats(
+        &self,
+        formats: &[(&str, &[u8])],
+    ) -> Result<(), Error> {
+        self.write_formats_to(Selection::Clipboard, formats)
+    }
+
+    /// Writes the given contents to the given [`Selection`], offering each
+    /// one in its [`format`] at once.
+    pub fn write_formats_to(
+        &self,
+        selection: Selection,
+        formats: &[(&str, &[u8])],
+    ) -> Result<(), Error> {
+        self.raw.write_formats_to(selection, formats)
+    }
+
+    /// Offers the clipboard in the given [`format`]s, without producing any
+    /// contents until a reader asks for one of them.
+    ///
+    /// `provider` is called with the requested format, possibly from another
+    /// thread.
+    pub fn write_lazy(
+        &self,
+        formats: &[&str],
+        provider: impl Fn(&str) -> Option<Vec<u8>> + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        self.write_lazy_to(Selection::Clipboard, formats, provider)
+    }
+
+    /// Offers the given [`Selection`] in the given [`format`]s, without
+    /// producing any contents until a reader asks for one of them.
+    pub fn write_lazy_to(
+        &self,
+        selection: Selection,
+        formats: &[&str],
+        provider: impl Fn(&str) -> Option<Vec<u8>> + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        self.raw
+            .write_lazy_to(selection, formats, Arc::new(provider))
+    }
+
+    /// Offers the clipboard in the given [`format`], streaming the contents
+    /// from a reader opened by `open` whenever they are requested.
+    pub fn write_from_reader<R: Read + Send + 'static>(
+        &self,
+        format: &str,
+        open: impl Fn() -> io::Result<R> + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        self.write_from_reader_to(Selection::Clipboard, format, open)
+    }
+
+    /// Offers the given [`Selection`] in the given [`format`], streaming the
+    /// contents from a reader opened by `open` whenever they are requested.
+    ///
+    /// Backends that cannot stream read the whole contents upfront.
+    pub fn write_from_reader_to<R: Read + Send + 'static>(
+        &self,
+        selection: Selection,
+        format: &str,
+        open: impl Fn() -> io::Result<R> + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        let source: Source = Arc::new(move || {
+            open().map(|reader| Box::new(reader) as Box<dyn Read + Send>)
+        });
+
+        self.raw.write_from_reader_to(selection, format, source)
+    }
+
+    /// Reads the clipboard contents as text without blocking, completing once
+    /// the transfer is done.
+    pub fn read_async(&self) -> Task<String> {
+        self.read_async_with(Selection::Clipboard, &ReadOptions::default())
+    }
+
+    /// Reads the contents of the given [`Selection`] as text with the given
+    /// [`ReadOptions`], without blocking.
+    ///
+    /// Dropping the [`Task`] only cancels the read if the options carry a
+    /// [`CancelToken`].
+    pub fn read_async_with(
+        &self,
+        selection: Selection,
+        options: &ReadOptions,
+    ) -> Task<String> {
+        Arc::clone(&self.raw).read_async(selection, options.clone())
+    }
+
+    /// Reads the clipboard contents in the given [`format`] without blocking,
+    /// completing once the transfer is done.
+    pub fn read_format_async(&self, format: &str) -> Task<Vec<u8>> {
+        self.read_format_async_with(
+            Selection::Clipboard,
+            format,
+            &ReadOptions::default(),
+        )
+    }
+
+    /// Reads the contents of the given [`Selection`] in the given [`format`]
+    /// with the given [`ReadOptions`], without blocking, like
+    /// [`Clipboard::read_async_with`].
+    pub fn read_format_async_with(
+        &self,
+        selection: Selection,
+        format: &str,
+        options: &ReadOptions,
+    ) -> Task<Vec<u8>> {
+        Arc::clone(&self.raw).read_format_async(
+            selection,
+            String::from(format),
+            options.clone(),
+        )
+    }
+
+    /// Returns the formats offered by the current clipboard owner without
+    /// blocking.
+    pub fn available_formats_async(&self) -> Task<Vec<String>> {
+        Arc::clone(&self.raw).available_formats_async(Selection::Clipboard)
+    }
+
+    /// Writes the given text to the clipboard without blocking.
+    pub fn write_async(&self, contents: String) -> Task<()> {
+        let clipboard = self.clone();
+
+        Task::spawn(None, move || clipboard.write(contents))
+    }
+
+    /// Returns a receiver that is notified with the [`Selection`] whose owner
+    /// or contents changed, every time it happens.
+    ///
+    /// Fails with [`Error::Unsupported`] if the backend cannot report changes.
+    pub fn subscribe(&self) -> Result<mpsc::Receiver<Selection>, Error> {
+        self.raw.subscribe()
+    }
+
+    /// Hands the clipboard contents over to the system, so they stay
+    /// available once the process exits.
+    ///
+    /// On X11, this asks the clipboard manager to save them, waiting until it
+    /// is done. Fails with [`Error::Unsupported`] if that is not possible.
+    pub fn persist(&self) -> Result<(), Error> {
+        self.raw.persist()
+    }
+
+    /// Sets whether the clipboard contents are handed over to the system when
+    /// the backend connection is dropped, like [`Clipboard::persist`] does.
+    ///
+    /// On X11, the clipboards of every window share a connection, so this
+    /// applies to all of them.
+    pub fn set_persist_on_drop(&self, persist: bool) -> Result<(), Error> {
+        self.raw.set_persist_on_drop(persist)
+    }
+
+    /// Serves the pending requests of other clients and completes the
+    /// pending `*_async` calls, without blocking.
+    ///
+    /// It only does anything for a clipboard obtained with
+    /// [`Clipboard::connect_without_thread`].
+    pub fn dispatch_pending(&self) -> Result<(), Error> {
+        self.raw.dispatch_pending()
+    }
+
+    /// Returns the file descriptor that becomes readable once
+    /// [`Clipboard::dispatch_pending`] has work to do, if the clipboard is
+    /// not served by threads of its own.
+    #[cfg(unix)]
+    pub fn fd(&self) -> Option<BorrowedFd<'_>> {
+        self.raw.fd()
+    }
+
+    /// Returns when [`Clipboard::dispatch_pending`] must be called next, for
+    /// timeouts and expiries, if ever.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.raw.next_deadline()
     }
 }
 
 impl Clipboard {
-    pub fn read_primary(&self) -> Option<Result<String, Box<dyn Error>>> {
-        self.raw.read_primary()
+    pub fn read_primary(&self) -> Option<Result<String, Error>> {
+        supported(self.read_from(Selection::Primary))
     }
 
-    pub fn write_primary(&mut self, contents: String) -> Option<Result<(), Box<dyn Error>>> {
-        self.raw.write_primary(contents)
+    pub fn write_primary(&self, contents: String) -> Option<Result<(), Error>> {
+        supported(self.write_to(Selection::Primary, contents))
     }
 }
 
-pub trait ClipboardProvider {
-    fn read(&self) -> Result<String, Box<dyn Error>>;
+/// A clipboard backend.
+///
+/// Providers are shared by every thread using the [`Clipboard`], which may
+/// call them concurrently, so they must be [`Send`] and [`Sync`].
+pub trait ClipboardProvider: Send + Sync + 'static {
+    fn read(&self) -> Result<String, Error>;
+
+    fn write(&self, contents: String) -> Result<(), Error>;
+
+    /// Returns what the provider supports.
+    ///
+    /// By default, only text in the clipboard is reported.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            formats: vec![String::from(format::TEXT)],
+            ..Capabilities::default()
+        }
+    }
+
+    /// Reads the text contents of the given [`Selection`].
+    ///
+    /// Only the clipboard is supported by default.
+    fn read_from(&self, selection: Selection) -> Result<String, Error> {
+        match selection {
+            Selection::Clipboard => self.read(),
+            _ => Err(Error::Unsupported),
+        }
+    }
+
+    /// Writes the given text to the given [`Selection`].
+    ///
+    /// Only the clipboard is supported by default.
+    fn write_to(
+        &self,
+        selection: Selection,
+        contents: String,
+    ) -> Result<(), Error> {
+        match selection {
+            Selection::Clipboard => self.write(contents),
+            _ => Err(Error::Unsupported),
+        }
+    }
+
+    /// Writes secret text to the given [`Selection`], asking clipboard
+    /// managers not to record it.
+    ///
+    /// Unsupported by default, so secrets never end up in a clipboard history
+    /// without their hint.
+    fn write_sensitive_to(
+        &self,
+        _selection: Selection,
+        _contents: String,
+    ) -> Result<(), Error> {
+        Err(Error::Unsupported)
+    }
+
+    /// Writes text to the given [`Selection`], clearing it once `expiry`
+    /// passes if we still own it.
+    fn write_with_expiry_to(
+        &self,
+        _selection: Selection,
+        _contents: String,
+        _expiry: Duration,
+    ) -> Result<(), Error> {
+        Err(Error::Unsupported)
+    }
+
+    fn read_format_from(
+        &self,
+        selection: Selection,
+        format: &str,
+    ) -> Result<Vec<u8>, Error> {
+        if format::is_text(format) {
+            self.read_from(selection).map(String::into_bytes)
+        } else {
+            Err(Error::UnsupportedFormat)
+        }
+    }
+
+    /// Reads the text contents of the given [`Selection`] with the given
+    /// [`ReadOptions`].
+    ///
+    /// By default, cancellation is only honored before the read, and the size
+    /// limit after it.
+    fn read_with(
+        &self,
+        selection: Selection,
+        options: &ReadOptions,
+    ) -> Result<String, Error> {
+        if options.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
+        let text = self.read_from(selection)?;
+
+        if options.exceeds(text.len()) {
+            return Err(Error::TooLarge);
+        }
+
+        Ok(text)
+    }
+
+    /// Reads the contents of the given [`Selection`] in the given format with
+    /// the given [`ReadOptions`].
+    ///
+    /// By default, cancellation is only honored before the read, and the size
+    /// limit after it.
+    fn read_format_with(
+        &self,
+        selection: Selection,
+        format: &str,
+        options: &ReadOptions,
+    ) -> Result<Vec<u8>, Error> {
+        if options.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
+        let data = self.read_format_from(selection, format)?;
+
+        if options.exceeds(data.len()) {
+            return Err(Error::TooLarge);
+        }
+
+        Ok(data)
+    }
+
+    /// Streams the contents of the given [`Selection`] in the given format
+    /// into `output`, returning the number of bytes written.
+    ///
+    /// By default, the contents are read at once and then written.
+    fn read_to(
+        &self,
+        selection: Selection,
+        format: &str,
+        output: &mut dyn Write,
+        options: &ReadOptions,
+    ) -> Result<usize, Error> {
+        let data = self.read_format_with(selection, format, options)?;
+
+        output.write_all(&data)?;
+
+        Ok(data.len())
+    }
+
+    fn available_formats_in(
+        &self,
+        selection: Selection,
+    ) -> Result<Vec<String>, Error> {
+        match self.read_from(selection) {
+            Ok(_) => Ok(vec![String::from(format::TEXT)]),
+            Err(Error::Empty) => Ok(Vec::new()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Reads the text contents of the given [`Selection`] with the given
+    /// [`ReadOptions`], without blocking.
+    ///
+    /// By default, the blocking read runs in the background.
+    fn read_async(
+        self: Arc<Self>,
+        selection: Selection,
+        options: ReadOptions,
+    ) -> Task<String> {
+        Task::spawn(options.cancel.clone(), move || {
+            self.read_with(selection, &options)
+        })
+    }
+
+    /// Reads the contents of the given [`Selection`] in the given format with
+    /// the given [`ReadOptions`], without blocking.
+    ///
+    /// By default, the blocking read runs in the background.
+    fn read_format_async(
+        self: Arc<Self>,
+        selection: Selection,
+        format: String,
+        options: ReadOptions,
+    ) -> Task<Vec<u8>> {
+        Task::spawn(options.cancel.clone(), move || {
+            self.read_format_with(selection, &format, &options)
+        })
+    }
+
+    /// Returns the formats offered by the current owner of the given
+    /// [`Selection`] without blocking.
+    ///
+    /// By default, the blocking call runs in the background.
+    fn available_formats_async(
+        self: Arc<Self>,
+        selection: Selection,
+    ) -> Task<Vec<String>> {
+        Task::spawn(None, move || self.available_formats_in(selection))
+    }
+
+    fn write_formats_to(
+        &self,
+        selection: Selection,
+        formats: &[(&str, &[u8])],
+    ) -> Result<(), Error> {
+        let (_, text) = formats
+            .iter()
+            .find(|(format, _)| format::is_text(format))
+            .ok_or(Error::UnsupportedFormat)?;
+
+        let text = String::from_utf8(text.to_vec())
+            .map_err(|_| Error::InvalidEncoding)?;
+
+        self.write_to(selection, text)
+    }
+
+    /// Offers the given [`Selection`] in the given formats, calling the
+    /// [`Provider`] only when they are requested.
+    ///
+    /// By default, every format is produced upfront.
+    fn write_lazy_to(
+        &self,
+        selection: Selection,
+        formats: &[&str],
+        provider: Provider,
+    ) -> Result<(), Error> {
+        let contents: Vec<(&str, Vec<u8>)> = formats
+            .iter()
+            .filter_map(|format| Some((*format, provider(format)?)))
+            .collect();
 
-    fn write(&mut self, contents: String) -> Result<(), Box<dyn Error>>;
+        let formats: Vec<(&str, &[u8])> = contents
+            .iter()
+            .map(|(format, data)| (*format, data.as_slice()))
+            .collect();
 
-    fn read_primary(&self) -> Option<Result<String, Box<dyn Error>>> {
+        self.write_formats_to(selection, &formats)
+    }
+
+    /// Offers the given [`Selection`] in the given format, reading the
+    /// contents from the [`Source`] when they are requested.
+    ///
+    /// By default, the contents are read upfront.
+    fn write_from_reader_to(
+        &self,
+        selection: Selection,
+        format: &str,
+        source: Source,
+    ) -> Result<(), Error> {
+        let mut data = Vec::new();
+
+        let _ = source()?.read_to_end(&mut data)?;
+
+        self.write_formats_to(selection, &[(format, &data)])
+    }
+
+    fn subscribe(&self) -> Result<mpsc::Receiver<Selection>, Error> {
+        Err(Error::Unsupported)
+    }
+
+    /// Hands the clipboard contents over to the system, so they outlive the
+    /// process.
+    ///
+    /// By default, this only succeeds if the contents are already
+    /// [`persistent`](Capabilities::persistent).
+    fn persist(&self) -> Result<(), Error> {
+        if self.capabilities().persistent {
+            Ok(())
+        } else {
+            Err(Error::Unsupported)
+        }
+    }
+
+    /// Sets whether the clipboard contents are handed over to the system on
+    /// drop.
+    ///
+    /// By default, this only succeeds if the contents are already
+    /// [`persistent`](Capabilities::persistent).
+    fn set_persist_on_drop(&self, _persist: bool) -> Result<(), Error> {
+        if self.capabilities().persistent {
+            Ok(())
+        } else {
+            Err(Error::Unsupported)
+        }
+    }
+
+    /// Serves the provider without blocking, if nothing else does.
+    ///
+    /// By default, there is nothing to do.
+    fn dispatch_pending(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Returns the file descriptor that becomes readable once
+    /// [`ClipboardProvider::dispatch_pending`] has work to do.
+    ///
+    /// By default, there is none.
+    #[cfg(unix)]
+    fn fd(&self) -> Option<BorrowedFd<'_>> {
         None
     }
 
-    fn write_primary(&mut self, _contents: String) -> Option<Result<(), Box<dyn Error>>> {
+    /// Returns when [`ClipboardProvider::dispatch_pending`] must be called
+    /// next.
+    ///
+    /// By default, never.
+    fn next_deadline(&self) -> Option<Instant> {
         None
     }
 }
+
+/// Turns an unsupported operation into `None`, like the `*_primary` methods
+/// have always reported it.
+fn supported<T>(result: Result<T, Error>) -> Option<Result<T, Error>> {
+    match result {
+        Err(Error::Unsupported) => None,
+        result => Some(result),
+    }
+}
@@ -0,0 +1,394 @@
+use crate::{format, Capabilities, ClipboardProvider, Error, Selection};
+
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+/// A failure that a [`MemoryClipboard`] reports on every read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadFailure {
+    /// Fail with [`Error::Timeout`], like an unresponsive owner.
+    Timeout,
+    /// Fail with [`Error::Empty`], like a clipboard without owner.
+    Empty,
+    /// Fail with [`Error::InvalidEncoding`], like an owner sending bad text.
+    InvalidEncoding,
+}
+
+/// A clipboard that only lives in memory, meant to test copy and paste
+/// flows without a window.
+///
+/// Every selection is supported and keeps its own contents. Clones share
+/// the same contents, so a test can keep one to inspect the clipboard after
+/// handing another to [`Clipboard::from_provider`].
+///
+/// [`Clipboard::from_provider`]: crate::Clipboard::from_provider
+#[derive(Debug, Clone, Default)]
+pub struct MemoryClipboard {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    contents: HashMap<Selection, Vec<(String, Vec<u8>)>>,
+    changes: HashMap<Selection, u64>,
+    failure: Option<ReadFailure>,
+    subscribers: Vec<mpsc::Sender<Selection>>,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns how many times the given [`Selection`] was written.
+    pub fn change_count(&self, selection: Selection) -> u64 {
+        self.state()
+            .changes
+            .get(&selection)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Makes every following read fail, until `None` is given.
+    pub fn fail_reads(&self, failure: Option<ReadFailure>) {
+        self.state().failure = failure;
+    }
+
+    /// Empties the given [`Selection`], like its owner going away.
+    pub fn clear(&self, selection: Selection) {
+        let mut state = self.state();
+
+        if state.contents.remove(&selection).is_some() {
+            state.notify(selection);
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // Every update is applied at once, so a poisoned state is consistent
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn contents(
+        &self,
+        selection: Selection,
+    ) -> Result<Vec<(String, Vec<u8>)>, Error> {
+        let state = self.state();
+
+        match state.failure {
+            Some(ReadFailure::Timeout) => Err(Error::Timeout),
+            Some(ReadFailure::Empty) => Err(Error::Empty),
+            Some(ReadFailure::InvalidEncoding) => Err(Error::InvalidEncoding),
+            None => state.contents.get(&selection).cloned().ok_or(Error::Empty),
+        }
+    }
+}
+
+impl State {
+    /// Replaces the contents of the given [`Selection`], returning its new
+    /// change count.
+    fn store(
+        &mut self,
+        selection: Selection,
+        formats: &[(&str, &[u8])],
+    ) -> u64 {
+        let _ = self.contents.insert(
+            selection,
+            formats
+                .iter()
+                .map(|(format, data)| (String::from(*format), data.to_vec()))
+                .collect(),
+        );
+
+        self.notify(selection);
+
+        self.changes[&selection]
+    }
+
+    fn notify(&mut self, selection: Selection) {
+        *self.changes.entry(selection).or_default() += 1;
+
+        self.subscribers
+            .retain(|subscriber| subscriber.send(selection).is_ok());
+    }
+}
+
+impl ClipboardProvider for MemoryClipboard {
+    fn read(&self) -> Result<String, Error> {
+        self.read_from(Selection::Clipboard)
+    }
+
+    fn write(&self, contents: String) -> Result<(), Error> {
+        self.write_to(Selection::Clipboard, contents)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            primary: true,
+            secondary: true,
+            formats: vec![String::from(format::TEXT)],
+            any_format: true,
+            change_notifications: true,
+            lazy_data: false,
+            persistent: false,
+        }
+    }
+
+    fn read_from(&self, selection: Selection) -> Result<String, Error> {
+        let text = self.read_format_from(selection, format::TEXT)?;
+
+        String::from_utf8(text).map_err(|_| Error::InvalidEncoding)
+    }
+
+    fn write_to(
+        &self,
+        selection: Selection,
+        contents: String,
+    ) -> Result<(), Error> {
+        self.write_formats_to(selection, &[(format::TEXT, contents.as_bytes())])
+    }
+
+    fn read_format_from(
+        &self,
+        selection: Selection,
+        format: &str,
+    ) -> Result<Vec<u8>, Error> {
+        self.contents(selection)?
+            .into_iter()
+            .find(|(offered, _)| {
+                offered == format
+                    || format::is_text(format) && format::is_text(offered)
+            })
+            .map(|(_, data)| data)
+            .ok_or(Error::UnsupportedFormat)
+    }
+
+    fn available_formats_in(
+        &self,
+        selection: Selection,
+    ) -> Result<Vec<String>, Error> {
+        match self.contents(selection) {
+            Ok(contents) => {
+                Ok(contents.into_iter().map(|(format, _)| format).collect())
+            }
+            Err(Error::Empty) => Ok(Vec::new()),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn write_formats_to(
+        &self,
+        selection: Selection,
+        formats: &[(&str, &[u8])],
+    ) -> Result<(), Error> {
+        let _ = self.state().store(selection, formats);
+
+        Ok(())
+    }
+
+    fn write_sensitive_to(
+        &self,
+        selection: Selection,
+        contents: String,
+    ) -> Result<(), Error> {
+        self.write_formats_to(
+            selection,
+            &[
+                (format::TEXT, contents.as_bytes()),
+                (format::PASSWORD_MANAGER_HINT, b"secret"),
+            ],
+        )
+    }
+
+    fn write_with_expiry_to(
+        &self,
+        selection: Selection,
+        contents: String,
+        expiry: Duration,
+    ) -> Result<(), Error> {
+        // Another write may land right after ours
+        let written = self
+            .state()
+            .store(selection, &[(format::TEXT, contents.as_bytes())]);
+        let clipboard = self.clone();
+
+        let _ = thread::spawn(move || {
+            thread::sleep(expiry);
+
+            // Any later write or clear bumps the change count
+            if clipboard.change_count(selection) == written {
+                clipboard.clear(selection);
+            }
+        });
+
+        Ok(())
+    }
+
+    fn subscribe(&self) -> Result<mpsc::Receiver<Selection>, Error> {
+        let (sender, receiver) = mpsc::channel();
+
+        self.state().subscribers.push(sender);
+
+        Ok(receiver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Clipboard;
+
+    fn clipboard() -> (MemoryClipboard, Clipboard) {
+        let memory = MemoryClipboard::new();
+        let clipboard = Clipboard::from_provider(Box::new(memory.clone()));
+
+        (memory, clipboard)
+    }
+
+    #[test]
+    fn reads_text_through_any_alias() {
+        let (_, clipboard) = clipboard();
+
+        clipboard
+            .write_formats(&[
+                ("UTF8_STRING", b"hi"),
+                ("text/html", b"<b>hi</b>"),
+            ])
+            .unwrap();
+
+        assert_eq!(clipboard.read().unwrap(), "hi");
+        assert_eq!(clipboard.read_format("text/plain").unwrap(), b"hi");
+        assert_eq!(clipboard.read_format("text/html").unwrap(), b"<b>hi</b>");
+        assert!(matches!(
+            clipboard.read_format("image/png"),
+            Err(Error::UnsupportedFormat)
+        ));
+    }
+
+    #[test]
+    fn keeps_selections_apart() {
+        let (memory, clipboard) = clipboard();
+
+        clipboard.write(String::from("clipboard")).unwrap();
+        clipboard
+            .write_to(Selection::Primary, String::from("primary"))
+            .unwrap();
+        clipboard
+            .write_to(Selection::Primary, String::from("again"))
+            .unwrap();
+
+        assert_eq!(clipboard.read().unwrap(), "clipboard");
+        assert_eq!(clipboard.read_from(Selection::Primary).unwrap(), "again");
+        assert!(matches!(
+            clipboard.read_from(Selection::Secondary),
+            Err(Error::Empty)
+        ));
+
+        assert_eq!(memory.change_count(Selection::Clipboard), 1);
+        assert_eq!(memory.change_count(Selection::Primary), 2);
+        assert_eq!(memory.change_count(Selection::Secondary), 0);
+    }
+
+    #[test]
+    fn fails_reads_on_demand() {
+        let (memory, clipboard) = clipboard();
+
+        clipboard.write(String::from("hi")).unwrap();
+
+        memory.fail_reads(Some(ReadFailure::Timeout));
+        assert!(matches!(clipboard.read(), Err(Error::Timeout)));
+
+        memory.fail_reads(Some(ReadFailure::Empty));
+        assert!(matches!(clipboard.read(), Err(Error::Empty)));
+
+        memory.fail_reads(Some(ReadFailure::InvalidEncoding));
+        assert!(matches!(clipboard.read(), Err(Error::InvalidEncoding)));
+
+        memory.fail_reads(None);
+        assert_eq!(clipboard.read().unwrap(), "hi");
+    }
+
+    #[test]
+    fn clears_selections() {
+        let (memory, clipboard) = clipboard();
+
+        clipboard.write(String::from("hi")).unwrap();
+        memory.clear(Selection::Clipboard);
+
+        assert!(matches!(clipboard.read(), Err(Error::Empty)));
+        assert!(clipboard.available_formats().unwrap().is_empty());
+        assert_eq!(memory.change_count(Selection::Clipboard), 2);
+
+        // Clearing an empty selection changes nothing
+        memory.clear(Selection::Clipboard);
+        assert_eq!(memory.change_count(Selection::Clipboard), 2);
+    }
+
+    #[test]
+    fn notifies_subscribers() {
+        let (memory, clipboard) = clipboard();
+        let changes = clipboard.subscribe().unwrap();
+
+        clipboard.write(String::from("hi")).unwrap();
+        clipboard
+            .write_to(Selection::Primary, String::from("hi"))
+            .unwrap();
+        memory.clear(Selection::Clipboard);
+
+        assert_eq!(
+            changes.try_iter().collect::<Vec<_>>(),
+            [
+                Selection::Clipboard,
+                Selection::Primary,
+                Selection::Clipboard
+            ]
+        );
+    }
+
+    #[test]
+    fn offers_sensitive_text_with_its_hint() {
+        let (_, clipboard) = clipboard();
+
+        clipboard.write_sensitive(String::from("hunter2")).unwrap();
+
+        assert_eq!(clipboard.read().unwrap(), "hunter2");
+        assert_eq!(
+            clipboard
+                .read_format(format::PASSWORD_MANAGER_HINT)
+                .unwrap(),
+            b"secret"
+        );
+    }
+
+    #[test]
+    fn expires_unless_overwritten() {
+        let (_, clipboard) = clipboard();
+        let changes = clipboard.subscribe().unwrap();
+
+        clipboard
+            .write_with_expiry_to(
+                Selection::Primary,
+                String::from("secret"),
+                Duration::ZERO,
+            )
+            .unwrap();
+        clipboard
+            .write_to(Selection::Primary, String::from("kept"))
+            .unwrap();
+        clipboard
+            .write_with_expiry(String::from("secret"), Duration::ZERO)
+            .unwrap();
+
+        // The write of the clipboard is followed by its expiry
+        let _ = changes
+            .iter()
+            .filter(|selection| *selection == Selection::Clipboard)
+            .nth(1);
+
+        assert!(matches!(clipboard.read(), Err(Error::Empty)));
+        assert_eq!(clipboard.read_from(Selection::Primary).unwrap(), "kept");
+    }
+}
@@ -1,42 +1,41 @@
-use crate::ClipboardProvider;
+use crate::{Capabilities, ClipboardProvider, Error};
 
 use raw_window_handle::HasDisplayHandle;
-use std::error::Error;
 
 pub fn connect<W: HasDisplayHandle>(
     _window: &W,
-) -> Result<Box<dyn ClipboardProvider>, Box<dyn Error>> {
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    connect_from_env()
+}
+
+pub fn connect_without_thread<W: HasDisplayHandle>(
+    window: &W,
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    connect(window)
+}
+
+pub fn connect_from_env() -> Result<Box<dyn ClipboardProvider>, Error> {
     Ok(Box::new(Clipboard::new()?))
 }
 
 pub struct Clipboard;
 
 impl Clipboard {
-    pub fn new() -> Result<Clipboard, Box<dyn Error>> {
+    pub fn new() -> Result<Clipboard, Error> {
         Ok(Self)
     }
 }
 
-#[derive(Debug)]
-#[allow(non_camel_case_types)]
-pub enum AndroidClipboardError {
-    Unimplemented,
-}
-
-impl std::fmt::Display for AndroidClipboardError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Unimplemented")
+impl ClipboardProvider for Clipboard {
+    fn read(&self) -> Result<String, Error> {
+        Err(Error::BackendUnavailable)
     }
-}
 
-impl Error for AndroidClipboardError {}
-
-impl ClipboardProvider for Clipboard {
-    fn read(&self) -> Result<String, Box<dyn Error>> {
-        Err(Box::new(AndroidClipboardError::Unimplemented))
+    fn write(&self, _contents: String) -> Result<(), Error> {
+        Err(Error::BackendUnavailable)
     }
 
-    fn write(&mut self, contents: String) -> Result<(), Box<dyn Error>> {
-        Err(Box::new(AndroidClipboardError::Unimplemented))
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
     }
 }
@@ -1,4 +1,4 @@
-use crate::ClipboardProvider;
+use crate::{Capabilities, ClipboardProvider, Error};
 
 use raw_window_handle::HasDisplayHandle;
 
@@ -6,25 +6,30 @@ struct Dummy;
 
 pub fn connect<W: HasDisplayHandle>(
     _window: &W,
-) -> Result<Box<dyn ClipboardProvider>, Box<dyn std::error::Error>> {
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    connect_from_env()
+}
+
+pub fn connect_without_thread<W: HasDisplayHandle>(
+    window: &W,
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    connect(window)
+}
+
+pub fn connect_from_env() -> Result<Box<dyn ClipboardProvider>, Error> {
     Ok(Box::new(Dummy))
 }
 
 impl ClipboardProvider for Dummy {
-    fn read(&self) -> Result<String, Box<dyn std::error::Error>> {
-        Err(Box::new(Error::Unimplemented))
+    fn read(&self) -> Result<String, Error> {
+        Err(Error::BackendUnavailable)
     }
 
-    fn write(
-        &mut self,
-        _contents: String,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        Err(Box::new(Error::Unimplemented))
+    fn write(&self, _contents: String) -> Result<(), Error> {
+        Err(Error::BackendUnavailable)
     }
-}
 
-#[derive(Debug, Clone, Copy, thiserror::Error)]
-enum Error {
-    #[error("unimplemented")]
-    Unimplemented,
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
 }
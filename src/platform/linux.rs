@@ -1,37 +1,104 @@
-use crate::ClipboardProvider;
+use crate::{ClipboardProvider, Error};
 
-use raw_window_handle::{HasDisplayHandle, RawDisplayHandle};
-use std::error::Error;
+use raw_window_handle::HasDisplayHandle;
+use std::env;
+
+// Only the backends need these
+#[cfg(any(feature = "x11", feature = "wayland"))]
+use {
+    crate::{
+        format, Capabilities, Provider, ReadOptions, Selection, Source, Task,
+    },
+    raw_window_handle::RawDisplayHandle,
+    std::io::Write,
+    std::os::fd::BorrowedFd,
+    std::sync::{mpsc, Arc},
+    std::time::{Duration, Instant},
+};
 
 #[cfg(feature = "wayland")]
 pub use clipboard_wayland as wayland;
 #[cfg(feature = "x11")]
 pub use clipboard_x11 as x11;
+#[cfg(feature = "x11")]
+use std::os::fd::AsFd;
 
-#[derive(Debug)]
-struct LinuxClipboardError;
+pub unsafe fn connect<W: HasDisplayHandle>(
+    window: &W,
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    let handle = window
+        .display_handle()
+        .map_err(|_| Error::BackendUnavailable)?;
 
-impl std::fmt::Display for LinuxClipboardError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("This window server's clipboard feature is not enabled")
-    }
-}
+    let clipboard = match handle.as_raw() {
+        #[cfg(feature = "wayland")]
+        RawDisplayHandle::Wayland(handle) => {
+            Box::new(wayland::Clipboard::connect(handle.display.as_ptr())) as _
+        }
+        // The windowing library consumes the events of its own connection,
+        // so every window shares a connection of ours instead
+        #[cfg(feature = "x11")]
+        RawDisplayHandle::Xlib(_) | RawDisplayHandle::Xcb(_) => {
+            Box::new(x11::Clipboard::connect_shared()?) as _
+        }
+        _ => Err(Error::BackendUnavailable)?,
+    };
 
-impl Error for LinuxClipboardError {}
+    Ok(clipboard)
+}
 
-pub unsafe fn connect<W: HasDisplayHandle>(
+pub unsafe fn connect_without_thread<W: HasDisplayHandle>(
     window: &W,
-) -> Result<Box<dyn ClipboardProvider>, Box<dyn Error>> {
-    let clipboard = match window.display_handle()?.as_raw() {
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    let handle = window
+        .display_handle()
+        .map_err(|_| Error::BackendUnavailable)?;
+
+    let clipboard = match handle.as_raw() {
         #[cfg(feature = "wayland")]
         RawDisplayHandle::Wayland(handle) => {
-            Box::new(wayland::Clipboard::connect(handle.display.as_ptr())) as _
+            Box::new(wayland::Clipboard::connect_without_thread(
+                handle.display.as_ptr(),
+            )?) as _
         }
         #[cfg(feature = "x11")]
         RawDisplayHandle::Xlib(_) | RawDisplayHandle::Xcb(_) => {
-            Box::new(x11::Clipboard::connect()?) as _
+            Box::new(x11::Clipboard::connect_shared_without_thread()?) as _
         }
-        _ => Err(LinuxClipboardError)?,
+        _ => Err(Error::BackendUnavailable)?,
+    };
+
+    Ok(clipboard)
+}
+
+/// The environment variable that forces a backend in [`connect_from_env`].
+const BACKEND_VAR: &str = "WINDOW_CLIPBOARD_BACKEND";
+
+pub fn connect_from_env() -> Result<Box<dyn ClipboardProvider>, Error> {
+    if let Ok(backend) = env::var(BACKEND_VAR) {
+        return connect_to(&backend);
+    }
+
+    let wayland = env::var_os("WAYLAND_DISPLAY").map(|_| connect_to("wayland"));
+
+    // XWayland is only a fallback, for when the compositor is out of reach
+    match wayland {
+        Some(Ok(clipboard)) => Ok(clipboard),
+        _ if env::var_os("DISPLAY").is_some() => connect_to("x11"),
+        Some(Err(error)) => Err(error),
+        None => Err(Error::BackendUnavailable),
+    }
+}
+
+/// Connects to the clipboard of the given backend, named like in
+/// [`BACKEND_VAR`].
+fn connect_to(backend: &str) -> Result<Box<dyn ClipboardProvider>, Error> {
+    let clipboard = match backend {
+        #[cfg(feature = "wayland")]
+        "wayland" => Box::new(wayland::Clipboard::connect_from_env()?) as _,
+        #[cfg(feature = "x11")]
+        "x11" => Box::new(x11::Clipboard::connect_shared()?) as _,
+        _ => Err(Error::BackendUnavailable)?,
     };
 
     Ok(clipboard)
@@ -39,38 +106,547 @@ pub unsafe fn connect<W: HasDisplayHandle>(
 
 #[cfg(feature = "wayland")]
 impl ClipboardProvider for wayland::Clipboard {
-    fn read(&self) -> Result<String, Box<dyn Error>> {
-        self.read()
+    fn read(&self) -> Result<String, Error> {
+        Ok(self.read()?)
+    }
+
+    fn write(&self, contents: String) -> Result<(), Error> {
+        Ok(self.write(contents)?)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            primary: self
+                .supports(wayland::Selection::Primary)
+                .unwrap_or(false),
+            secondary: false,
+            formats: formats(),
+            any_format: true,
+            change_notifications: true,
+            lazy_data: true,
+            persistent: false,
+        }
+    }
+
+    fn read_from(&self, selection: Selection) -> Result<String, Error> {
+        Ok(self.read_from(to_wayland(selection)?)?)
+    }
+
+    fn read_with(
+        &self,
+        selection: Selection,
+        options: &ReadOptions,
+    ) -> Result<String, Error> {
+        Ok(self.read_with(to_wayland(selection)?, &wayland_options(options))?)
+    }
+
+    fn write_to(
+        &self,
+        selection: Selection,
+        contents: String,
+    ) -> Result<(), Error> {
+        Ok(self.write_to(to_wayland(selection)?, contents)?)
+    }
+
+    fn read_format_from(
+        &self,
+        selection: Selection,
+        format: &str,
+    ) -> Result<Vec<u8>, Error> {
+        Ok(self.read_format_from(to_wayland(selection)?, format)?)
+    }
+
+    fn read_format_with(
+        &self,
+        selection: Selection,
+        format: &str,
+        options: &ReadOptions,
+    ) -> Result<Vec<u8>, Error> {
+        Ok(self.read_format_with(
+            to_wayland(selection)?,
+            format,
+            &wayland_options(options),
+        )?)
+    }
+
+    fn read_to(
+        &self,
+        selection: Selection,
+        format: &str,
+        output: &mut dyn Write,
+        options: &ReadOptions,
+    ) -> Result<usize, Error> {
+        Ok(self.read_to(
+            to_wayland(selection)?,
+            format,
+            output,
+            &wayland_options(options),
+        )?)
+    }
+
+    fn available_formats_in(
+        &self,
+        selection: Selection,
+    ) -> Result<Vec<String>, Error> {
+        Ok(self.available_formats_in(to_wayland(selection)?)?)
+    }
+
+    fn read_async(
+        self: Arc<Self>,
+        selection: Selection,
+        options: ReadOptions,
+    ) -> Task<String> {
+        let (task, completer) = Task::pending(options.cancel.clone());
+
+        match to_wayland(selection) {
+            Ok(selection) => wayland::Clipboard::read_async(
+                &self,
+                selection,
+                &wayland_options(&options),
+                move |text| completer.complete(text.map_err(Error::from)),
+            ),
+            Err(error) => completer.complete(Err(error)),
+        }
+
+        task
+    }
+
+    fn read_format_async(
+        self: Arc<Self>,
+        selection: Selection,
+        format: String,
+        options: ReadOptions,
+    ) -> Task<Vec<u8>> {
+        let (task, completer) = Task::pending(options.cancel.clone());
+
+        match to_wayland(selection) {
+            Ok(selection) => wayland::Clipboard::read_format_async(
+                &self,
+                selection,
+                &format,
+                &wayland_options(&options),
+                move |data| completer.complete(data.map_err(Error::from)),
+            ),
+            Err(error) => completer.complete(Err(error)),
+        }
+
+        task
+    }
+
+    fn available_formats_async(
+        self: Arc<Self>,
+        selection: Selection,
+    ) -> Task<Vec<String>> {
+        let (task, completer) = Task::pending(None);
+
+        match to_wayland(selection) {
+            Ok(selection) => wayland::Clipboard::available_formats_async(
+                &self,
+                selection,
+                move |formats| completer.complete(formats.map_err(Error::from)),
+            ),
+            Err(error) => completer.complete(Err(error)),
+        }
+
+        task
+    }
+
+    fn write_formats_to(
+        &self,
+        selection: Selection,
+        formats: &[(&str, &[u8])],
+    ) -> Result<(), Error> {
+        Ok(self.write_formats_to(to_wayland(selection)?, formats)?)
+    }
+
+    fn write_lazy_to(
+        &self,
+        selection: Selection,
+        formats: &[&str],
+        provider: Provider,
+    ) -> Result<(), Error> {
+        Ok(self.write_lazy_to(to_wayland(selection)?, formats, provider)?)
+    }
+
+    fn write_sensitive_to(
+        &self,
+        selection: Selection,
+        contents: String,
+    ) -> Result<(), Error> {
+        Ok(self.write_sensitive_to(to_wayland(selection)?, contents)?)
+    }
+
+    fn write_with_expiry_to(
+        &self,
+        selection: Selection,
+        contents: String,
+        expiry: Duration,
+    ) -> Result<(), Error> {
+        Ok(self.write_with_expiry_to(
+            to_wayland(selection)?,
+            contents,
+            expiry,
+        )?)
+    }
+
+    fn write_from_reader_to(
+        &self,
+        selection: Selection,
+        format: &str,
+        source: Source,
+    ) -> Result<(), Error> {
+        Ok(self.write_from_reader_to(to_wayland(selection)?, format, source)?)
+    }
+
+    fn subscribe(&self) -> Result<mpsc::Receiver<Selection>, Error> {
+        let (sender, receiver) = mpsc::channel();
+
+        self.watch(move |selection| {
+            let selection = match selection {
+                wayland::Selection::Clipboard => Selection::Clipboard,
+                wayland::Selection::Primary => Selection::Primary,
+            };
+
+            sender.send(selection).is_ok()
+        })?;
+
+        Ok(receiver)
+    }
+
+    fn dispatch_pending(&self) -> Result<(), Error> {
+        Ok(self.dispatch_pending()?)
     }
 
-    fn read_primary(&self) -> Option<Result<String, Box<dyn Error>>> {
-        Some(self.read_primary())
+    fn fd(&self) -> Option<BorrowedFd<'_>> {
+        self.fd()
     }
 
-    fn write(&mut self, contents: String) -> Result<(), Box<dyn Error>> {
-        self.write(contents)
+    fn next_deadline(&self) -> Option<Instant> {
+        self.next_deadline()
     }
+}
+
+/// The formats both backends exchange as they are, which the helpers of
+/// [`Clipboard`](crate::Clipboard) read and write.
+#[cfg(any(feature = "x11", feature = "wayland"))]
+fn formats() -> Vec<String> {
+    let mut formats = vec![format::TEXT, format::HTML, format::URI_LIST];
 
-    fn write_primary(&mut self, contents: String) -> Option<Result<(), Box<dyn Error>>> {
-        Some(self.write_primary(contents))
+    if cfg!(feature = "image") {
+        formats.push(format::PNG);
+    }
+
+    formats.into_iter().map(String::from).collect()
+}
+
+#[cfg(feature = "wayland")]
+fn wayland_options(options: &ReadOptions) -> wayland::ReadOptions {
+    let default = wayland::ReadOptions::default();
+
+    wayland::ReadOptions {
+        timeout: options.timeout.or(default.timeout),
+        cancel: options.cancel.as_ref().map(|token| Arc::clone(&token.0)),
+        max_bytes: options.max_bytes,
+    }
+}
+
+/// Wayland has no secondary selection.
+#[cfg(feature = "wayland")]
+fn to_wayland(selection: Selection) -> Result<wayland::Selection, Error> {
+    match selection {
+        Selection::Clipboard => Ok(wayland::Selection::Clipboard),
+        Selection::Primary => Ok(wayland::Selection::Primary),
+        Selection::Secondary => Err(Error::Unsupported),
     }
 }
 
 #[cfg(feature = "x11")]
 impl ClipboardProvider for x11::Clipboard {
-    fn read(&self) -> Result<String, Box<dyn Error>> {
-        self.read().map_err(Box::from)
+    fn read(&self) -> Result<String, Error> {
+        Ok(self.read()?)
+    }
+
+    fn write(&self, contents: String) -> Result<(), Error> {
+        Ok(self.write(contents)?)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            primary: true,
+            secondary: true,
+            formats: formats(),
+            any_format: true,
+            change_notifications: self.can_watch(),
+            lazy_data: true,
+            persistent: false,
+        }
+    }
+
+    fn read_from(&self, selection: Selection) -> Result<String, Error> {
+        Ok(self.read_from(to_x11(selection))?)
+    }
+
+    fn read_with(
+        &self,
+        selection: Selection,
+        options: &ReadOptions,
+    ) -> Result<String, Error> {
+        Ok(self.read_with(to_x11(selection), &x11_options(options))?)
+    }
+
+    fn write_to(
+        &self,
+        selection: Selection,
+        contents: String,
+    ) -> Result<(), Error> {
+        Ok(self.write_to(to_x11(selection), contents)?)
+    }
+
+    fn read_format_from(
+        &self,
+        selection: Selection,
+        format: &str,
+    ) -> Result<Vec<u8>, Error> {
+        Ok(self.read_format_from(to_x11(selection), format)?)
+    }
+
+    fn read_format_with(
+        &self,
+        selection: Selection,
+        format: &str,
+        options: &ReadOptions,
+    ) -> Result<Vec<u8>, Error> {
+        Ok(self.read_format_with(
+            to_x11(selection),
+            format,
+            &x11_options(options),
+        )?)
+    }
+
+    fn read_to(
+        &self,
+        selection: Selection,
+        format: &str,
+        output: &mut dyn Write,
+        options: &ReadOptions,
+    ) -> Result<usize, Error> {
+        Ok(self.read_to(
+            to_x11(selection),
+            format,
+            output,
+            &x11_options(options),
+        )?)
+    }
+
+    fn available_formats_in(
+        &self,
+        selection: Selection,
+    ) -> Result<Vec<String>, Error> {
+        Ok(self.available_formats_in(to_x11(selection))?)
+    }
+
+    fn read_async(
+        self: Arc<Self>,
+        selection: Selection,
+        options: ReadOptions,
+    ) -> Task<String> {
+        let (task, completer) = Task::pending(options.cancel.clone());
+
+        x11::Clipboard::read_async(
+            &self,
+            to_x11(selection),
+            &x11_options(&options),
+            move |text| completer.complete(text.map_err(Error::from)),
+        );
+
+        task
+    }
+
+    fn read_format_async(
+        self: Arc<Self>,
+        selection: Selection,
+        format: String,
+        options: ReadOptions,
+    ) -> Task<Vec<u8>> {
+        let (task, completer) = Task::pending(options.cancel.clone());
+
+        x11::Clipboard::read_format_async(
+            &self,
+            to_x11(selection),
+            &format,
+            &x11_options(&options),
+            move |data| completer.complete(data.map_err(Error::from)),
+        );
+
+        task
+    }
+
+    fn available_formats_async(
+        self: Arc<Self>,
+        selection: Selection,
+    ) -> Task<Vec<String>> {
+        let (task, completer) = Task::pending(None);
+
+        x11::Clipboard::available_formats_async(
+            &self,
+            to_x11(selection),
+            move |formats| completer.complete(formats.map_err(Error::from)),
+        );
+
+        task
+    }
+
+    fn write_formats_to(
+        &self,
+        selection: Selection,
+        formats: &[(&str, &[u8])],
+    ) -> Result<(), Error> {
+        Ok(self.write_formats_to(to_x11(selection), formats)?)
+    }
+
+    fn write_lazy_to(
+        &self,
+        selection: Selection,
+        formats: &[&str],
+        provider: Provider,
+    ) -> Result<(), Error> {
+        Ok(self.write_lazy_to(to_x11(selection), formats, provider)?)
+    }
+
+    fn write_sensitive_to(
+        &self,
+        selection: Selection,
+        contents: String,
+    ) -> Result<(), Error> {
+        Ok(self.write_sensitive_to(to_x11(selection), contents)?)
+    }
+
+    fn write_with_expiry_to(
+        &self,
+        selection: Selection,
+        contents: String,
+        expiry: Duration,
+    ) -> Result<(), Error> {
+        Ok(self.write_with_expiry_to(to_x11(selection), contents, expiry)?)
+    }
+
+    fn persist(&self) -> Result<(), Error> {
+        Ok(self.persist()?)
+    }
+
+    fn set_persist_on_drop(&self, persist: bool) -> Result<(), Error> {
+        x11::Clipboard::set_persist_on_drop(self, persist);
+
+        Ok(())
+    }
+
+    fn write_from_reader_to(
+        &self,
+        selection: Selection,
+        format: &str,
+        source: Source,
+    ) -> Result<(), Error> {
+        Ok(self.write_from_reader_to(to_x11(selection), format, source)?)
+    }
+
+    fn subscribe(&self) -> Result<mpsc::Receiver<Selection>, Error> {
+        let (sender, receiver) = mpsc::channel();
+
+        self.watch(move |selection| {
+            let selection = match selection {
+                x11::Selection::Clipboard => Selection::Clipboard,
+                x11::Selection::Primary => Selection::Primary,
+                x11::Selection::Secondary => Selection::Secondary,
+            };
+
+            sender.send(selection).is_ok()
+        })?;
+
+        Ok(receiver)
     }
 
-    fn read_primary(&self) -> Option<Result<String, Box<dyn Error>>> {
-        Some(self.read_primary().map_err(Box::from))
+    fn dispatch_pending(&self) -> Result<(), Error> {
+        Ok(self.dispatch_pending()?)
     }
 
-    fn write(&mut self, contents: String) -> Result<(), Box<dyn Error>> {
-        self.write(contents).map_err(Box::from)
+    // A worker thread already serves the connection
+    fn fd(&self) -> Option<BorrowedFd<'_>> {
+        (!self.is_threaded()).then(|| self.as_fd())
     }
 
-    fn write_primary(&mut self, contents: String) -> Option<Result<(), Box<dyn Error>>> {
-        Some(self.write_primary(contents).map_err(Box::from))
+    fn next_deadline(&self) -> Option<Instant> {
+        if self.is_threaded() {
+            None
+        } else {
+            self.next_deadline()
+        }
+    }
+}
+
+/// X11 keeps its own timeout unless one is given.
+#[cfg(feature = "x11")]
+fn x11_options(options: &ReadOptions) -> x11::ReadOptions {
+    let default = x11::ReadOptions::default();
+
+    x11::ReadOptions {
+        timeout: options.timeout.or(default.timeout),
+        cancel: options.cancel.as_ref().map(|token| Arc::clone(&token.0)),
+        max_bytes: options.max_bytes,
+    }
+}
+
+#[cfg(feature = "x11")]
+fn to_x11(selection: Selection) -> x11::Selection {
+    match selection {
+        Selection::Clipboard => x11::Selection::Clipboard,
+        Selection::Primary => x11::Selection::Primary,
+        Selection::Secondary => x11::Selection::Secondary,
+    }
+}
+
+#[cfg(feature = "wayland")]
+impl From<wayland::Error> for Error {
+    fn from(error: wayland::Error) -> Self {
+        match error {
+            wayland::Error::EmptySelection => Error::Empty,
+            wayland::Error::UnsupportedMimeType => Error::UnsupportedFormat,
+            wayland::Error::UnsupportedSelection => Error::Unsupported,
+            wayland::Error::ConnectionFailed(_) => Error::BackendUnavailable,
+            wayland::Error::Timeout => Error::Timeout,
+            wayland::Error::Cancelled => Error::Cancelled,
+            wayland::Error::TooLarge => Error::TooLarge,
+            wayland::Error::Io(error) => Error::Io(error),
+            wayland::Error::InvalidUtf8(_) => Error::InvalidEncoding,
+            wayland::Error::NoSeat
+            | wayland::Error::Unfocused
+            | wayland::Error::WorkerDied => Error::BackendUnavailable,
+        }
+    }
+}
+
+#[cfg(feature = "x11")]
+impl From<x11::Error> for Error {
+    fn from(error: x11::Error) -> Self {
+        use std::io;
+
+        match error {
+            x11::Error::Timeout => Error::Timeout,
+            x11::Error::Cancelled => Error::Cancelled,
+            x11::Error::TooLarge => Error::TooLarge,
+            x11::Error::Io(error) => Error::Io(error),
+            x11::Error::EmptySelection => Error::Empty,
+            x11::Error::UnsupportedTarget(_)
+            | x11::Error::UnexpectedType(_) => Error::UnsupportedFormat,
+            x11::Error::InvalidUtf8(_) => Error::InvalidEncoding,
+            x11::Error::InvalidOwner => Error::OwnershipLost,
+            x11::Error::MissingExtension(_)
+            | x11::Error::MissingClipboardManager => Error::Unsupported,
+            x11::Error::WorkerDied => Error::BackendUnavailable,
+            x11::Error::ConnectionFailed(_)
+            | x11::Error::ConnectionErrored(_)
+            | x11::Error::ReplyError(_)
+            | x11::Error::SelectionLocked
+            | x11::Error::SendError(_) => Error::Io(io::Error::other(error)),
+        }
     }
 }
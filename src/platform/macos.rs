@@ -1,20 +1,46 @@
-use crate::ClipboardProvider;
+use crate::{format, Capabilities, ClipboardProvider, Error};
 
 use raw_window_handle::HasDisplayHandle;
-use std::error::Error;
+use std::io;
 
 pub fn connect<W: HasDisplayHandle>(
     _window: &W,
-) -> Result<Box<dyn ClipboardProvider>, Box<dyn Error>> {
-    Ok(Box::new(clipboard_macos::Clipboard::new()?))
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    connect_from_env()
+}
+
+pub fn connect_without_thread<W: HasDisplayHandle>(
+    window: &W,
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    connect(window)
+}
+
+pub fn connect_from_env() -> Result<Box<dyn ClipboardProvider>, Error> {
+    Ok(Box::new(
+        clipboard_macos::Clipboard::new().map_err(from_macos)?,
+    ))
 }
 
 impl ClipboardProvider for clipboard_macos::Clipboard {
-    fn read(&self) -> Result<String, Box<dyn Error>> {
-        self.read()
+    fn read(&self) -> Result<String, Error> {
+        self.read().map_err(from_macos)
     }
 
-    fn write(&mut self, contents: String) -> Result<(), Box<dyn Error>> {
-        self.write(contents)
+    fn write(&self, contents: String) -> Result<(), Error> {
+        self.write(contents).map_err(from_macos)
     }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            formats: vec![String::from(format::TEXT)],
+            persistent: true,
+            ..Capabilities::default()
+        }
+    }
+}
+
+// `clipboard_macos` only reports string errors, which are neither `Send` nor
+// `Sync`; keep their message.
+fn from_macos(error: Box<dyn std::error::Error>) -> Error {
+    Error::Io(io::Error::other(error.to_string()))
 }
@@ -1,24 +1,53 @@
-use crate::ClipboardProvider;
+use crate::{format, Capabilities, ClipboardProvider, Error};
 
-use clipboard_win::{get_clipboard_string, set_clipboard_string};
+use clipboard_win::{
+    formats, get_clipboard_string, is_format_avail, set_clipboard_string,
+    ErrorCode,
+};
 use raw_window_handle::HasDisplayHandle;
 
-use std::error::Error;
+use std::io;
 
 pub fn connect<W: HasDisplayHandle>(
     _window: &W,
-) -> Result<Box<dyn ClipboardProvider>, Box<dyn Error>> {
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    connect_from_env()
+}
+
+pub fn connect_without_thread<W: HasDisplayHandle>(
+    window: &W,
+) -> Result<Box<dyn ClipboardProvider>, Error> {
+    connect(window)
+}
+
+pub fn connect_from_env() -> Result<Box<dyn ClipboardProvider>, Error> {
     Ok(Box::new(Clipboard))
 }
 
 pub struct Clipboard;
 
 impl ClipboardProvider for Clipboard {
-    fn read(&self) -> Result<String, Box<dyn Error>> {
-        Ok(get_clipboard_string()?)
+    fn read(&self) -> Result<String, Error> {
+        if !is_format_avail(formats::CF_UNICODETEXT) {
+            return Err(Error::Empty);
+        }
+
+        get_clipboard_string().map_err(from_windows)
     }
 
-    fn write(&mut self, contents: String) -> Result<(), Box<dyn Error>> {
-        Ok(set_clipboard_string(&contents)?)
+    fn write(&self, contents: String) -> Result<(), Error> {
+        set_clipboard_string(&contents).map_err(from_windows)
     }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            formats: vec![String::from(format::TEXT)],
+            persistent: true,
+            ..Capabilities::default()
+        }
+    }
+}
+
+fn from_windows(error: ErrorCode) -> Error {
+    Error::Io(io::Error::from_raw_os_error(error.raw_code()))
 }
@@ -0,0 +1,53 @@
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The options of a clipboard read.
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    /// How long to wait for the clipboard owner before failing with
+    /// [`Error::Timeout`], or the usual time of the backend if `None`.
+    ///
+    /// Use [`Duration::MAX`] to wait for as long as it takes.
+    ///
+    /// [`Error::Timeout`]: crate::Error::Timeout
+    pub timeout: Option<Duration>,
+    /// A token that fails the read with [`Error::Cancelled`] once cancelled.
+    ///
+    /// [`Error::Cancelled`]: crate::Error::Cancelled
+    pub cancel: Option<CancelToken>,
+    /// The most bytes to read before failing with [`Error::TooLarge`], or
+    /// no limit if `None`.
+    ///
+    /// [`Error::TooLarge`]: crate::Error::TooLarge
+    pub max_bytes: Option<usize>,
+}
+
+impl ReadOptions {
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    pub(crate) fn exceeds(&self, bytes: usize) -> bool {
+        self.max_bytes.is_some_and(|max_bytes| bytes > max_bytes)
+    }
+}
+
+/// A token that aborts a pending read from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(pub(crate) Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every read using this token, now and in the future.
+    pub fn cancel(&self) {
+        self.0.store(true, atomic::Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(atomic::Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{CancelToken, Error};
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// A clipboard operation running in the background, completing once the
+/// transfer is done.
+///
+/// It works with any async runtime. The X11 and Wayland backends complete it
+/// from the thread serving the clipboard. Other operations block, so with
+/// the `tokio` feature, the ones started inside a Tokio runtime run on its
+/// blocking pool; otherwise, they run on a thread of their own.
+///
+/// Dropping a pending read cancels it if its [`ReadOptions`] carry a
+/// [`CancelToken`]; otherwise, the transfer ends in the background and its
+/// result is dropped.
+///
+/// [`ReadOptions`]: crate::ReadOptions
+#[must_use = "tasks do nothing unless polled"]
+pub struct Task<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+    cancel: Option<CancelToken>,
+}
+
+struct Shared<T> {
+    result: Option<Result<T, Error>>,
+    waker: Option<Waker>,
+}
+
+/// Completes a [`Task`] once its operation is done.
+///
+/// Dropping it first fails the task with [`Error::BackendUnavailable`], since
+/// the backend went away in the middle of the operation.
+pub(crate) struct Completer<T> {
+    shared: Option<Arc<Mutex<Shared<T>>>>,
+}
+
+impl<T> Completer<T> {
+    pub(crate) fn complete(mut self, result: Result<T, Error>) {
+        if let Some(shared) = self.shared.take() {
+            finish(&shared, result);
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.take() {
+            finish(&shared, Err(Error::BackendUnavailable));
+        }
+    }
+}
+
+fn finish<T>(shared: &Mutex<Shared<T>>, result: Result<T, Error>) {
+    let mut shared = shared.lock().unwrap_or_else(|poison| poison.into_inner());
+
+    shared.result = Some(result);
+
+    if let Some(waker) = shared.waker.take() {
+        waker.wake();
+    }
+}
+
+impl<T: Send + 'static> Task<T> {
+    /// Returns a pending task, along with the [`Completer`] that completes it.
+    pub(crate) fn pending(cancel: Option<CancelToken>) -> (Self, Completer<T>) {
+        let shared = Arc::new(Mutex::new(Shared {
+            result: None,
+            waker: None,
+        }));
+
+        let completer = Completer {
+            shared: Some(Arc::clone(&shared)),
+        };
+
+        (Task { shared, cancel }, completer)
+    }
+
+    /// Runs the given blocking operation in the background.
+    pub(crate) fn spawn(
+        cancel: Option<CancelToken>,
+        operation: impl FnOnce() -> Result<T, Error> + Send + 'static,
+    ) -> Self {
+        let (task, completer) = Self::pending(cancel);
+        let run = move || completer.complete(operation());
+
+        #[cfg(feature = "tokio")]
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            drop(runtime.spawn_blocking(run));
+
+            return task;
+        }
+
+        if let Err(error) = thread::Builder::new()
+            .name(String::from("window_clipboard"))
+            .spawn(run)
+        {
+            finish(&task.shared, Err(Error::Io(error)));
+        }
+
+        task
+    }
+}
+
+impl<T> Future for Task<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(
+        self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+    ) -> Poll<Self::Output> {
+        let mut shared = self
+            .shared
+            .lock()
+            .unwrap_or_else(|poison| poison.into_inner());
+
+        match shared.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                shared.waker = Some(context.waker().clone());
+
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Drop for Task<T> {
+    fn drop(&mut self) {
+        if let Some(cancel) = &self.cancel {
+            cancel.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::task::Wake;
+
+    /// Wakes the thread blocked in [`block_on`].
+    struct Unpark(thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<T>(mut task: Task<T>) -> Result<T, Error> {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut context = Context::from_waker(&waker);
+
+        loop {
+            if let Poll::Ready(result) = Pin::new(&mut task).poll(&mut context)
+            {
+                return result;
+            }
+
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn completes_from_another_thread() {
+        let (task, completer) = Task::pending(None);
+
+        let _ = thread::spawn(move || completer.complete(Ok(42)));
+
+        assert_eq!(block_on(task).unwrap(), 42);
+    }
+
+    #[test]
+    fn fails_once_the_completer_is_dropped() {
+        let (task, completer) = Task::<()>::pending(None);
+
+        drop(completer);
+
+        assert!(matches!(block_on(task), Err(Error::BackendUnavailable)));
+    }
+
+    #[test]
+    fn cancels_on_drop() {
+        let cancel = CancelToken::new();
+        let (task, _completer) = Task::<()>::pending(Some(cancel.clone()));
+
+        drop(task);
+
+        assert!(cancel.is_cancelled());
+    }
+}
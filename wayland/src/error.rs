@@ -0,0 +1,32 @@
+use sctk::reexports::client::ConnectError;
+
+use std::io;
+use std::string::FromUtf8Error;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("connection failed: {0}")]
+    ConnectionFailed(#[from] ConnectError),
+    #[error("selection is empty")]
+    EmptySelection,
+    #[error("no supported mime type is offered")]
+    UnsupportedMimeType,
+    #[error("invalid utf8 string: {0}")]
+    InvalidUtf8(FromUtf8Error),
+    #[error("selection is not supported by the compositor")]
+    UnsupportedSelection,
+    #[error("no seat has received any input")]
+    NoSeat,
+    #[error("client does not have keyboard focus")]
+    Unfocused,
+    #[error("selection owner did not answer in time")]
+    Timeout,
+    #[error("read was cancelled")]
+    Cancelled,
+    #[error("selection is too large")]
+    TooLarge,
+    #[error("clipboard worker is dead")]
+    WorkerDied,
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+}
@@ -12,40 +12,738 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::error::Error;
+mod error;
+mod mime;
+mod state;
+mod worker;
+
+pub use error::Error;
+
+use crate::state::{Data, Sink, Transfer};
+use crate::worker::{Command, Local};
+
+use sctk::reexports::calloop::channel;
+use sctk::reexports::client::backend::Backend;
+use sctk::reexports::client::Connection;
+
 use std::ffi::c_void;
-use std::sync::{Arc, Mutex};
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsFd, BorrowedFd, OwnedFd};
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The longest a blocking call dispatches the worker at once, so it notices
+/// the reply it waits for.
+const DISPATCH_SLICE: Duration = Duration::from_millis(10);
+
+/// A selection of the Wayland compositor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Selection {
+    /// The regular clipboard, used by explicit copy and paste.
+    Clipboard,
+    /// The primary selection, set by selecting text.
+    Primary,
+}
+
+/// A function producing the contents of a selection in the given mime type.
+///
+/// It is only called once a client asks for the mime type, and it may be
+/// called again for every request. Returning `None` refuses the request.
+pub type Provider = Arc<dyn Fn(&str) -> Option<Vec<u8>> + Send + Sync>;
+
+/// A function opening a new reader over the contents of a selection.
+///
+/// It is called for every request, so every client reads the contents from
+/// the start.
+pub type Source =
+    Arc<dyn Fn() -> io::Result<Box<dyn Read + Send>> + Send + Sync>;
+
+/// The options of a read.
+#[derive(Debug, Clone)]
+pub struct ReadOptions {
+    /// How long to wait for the selection owner, or forever if `None`.
+    ///
+    /// Defaults to 3 seconds.
+    pub timeout: Option<Duration>,
+    /// Aborts the read with [`Error::Cancelled`] once set to `true`.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Aborts the read with [`Error::TooLarge`] past this many bytes.
+    pub max_bytes: Option<usize>,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            timeout: Some(Duration::from_secs(3)),
+            cancel: None,
+            max_bytes: None,
+        }
+    }
+}
+
+impl ReadOptions {
+    fn is_cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(atomic::Ordering::Relaxed))
+    }
+}
 
 pub struct Clipboard {
-    context: Arc<Mutex<smithay_clipboard::Clipboard>>,
+    commands: channel::Sender<Command>,
+    worker: Option<thread::JoinHandle<()>>,
+    /// The worker, unless it runs in a thread of its own.
+    local: Option<Mutex<Local>>,
+    fd: Option<OwnedFd>,
 }
 
 impl Clipboard {
+    /// Connect to the clipboard of the given Wayland display.
+    ///
+    /// The clipboard is served by its own thread with its own event queue.
+    ///
+    /// # Safety
+    ///
+    /// `display` must be a valid `*mut wl_display` pointer, and it must remain
+    /// valid for as long as the [`Clipboard`] is alive.
     pub unsafe fn connect(display: *mut c_void) -> Clipboard {
-        let context = Arc::new(Mutex::new(smithay_clipboard::Clipboard::new(
-            display as *mut _,
-        )));
+        let backend = Backend::from_foreign_display(display.cast());
+        let connection = Connection::from_backend(backend);
 
-        Clipboard { context }
+        let (commands, receiver) = channel::channel();
+        let worker = worker::spawn(connection, receiver);
+
+        Clipboard {
+            commands,
+            worker,
+            local: None,
+            fd: None,
+        }
     }
 
-    pub fn read(&self) -> Result<String, Box<dyn Error>> {
-        Ok(self.context.lock().unwrap().load()?)
+    /// Connect to the clipboard of the given Wayland display without
+    /// spawning any threads.
+    ///
+    /// The clipboard is only served while [`Clipboard::dispatch_pending`] is
+    /// called; usually, whenever [`Clipboard::fd`] becomes readable or
+    /// [`Clipboard::next_deadline`] passes. Blocking calls serve it until
+    /// they are done.
+    ///
+    /// # Safety
+    ///
+    /// `display` must be a valid `*mut wl_display` pointer, and it must remain
+    /// valid for as long as the [`Clipboard`] is alive.
+    pub unsafe fn connect_without_thread(
+        display: *mut c_void,
+    ) -> Result<Clipboard, Error> {
+        let backend = Backend::from_foreign_display(display.cast());
+
+        Self::local(Connection::from_backend(backend))
     }
 
-    pub fn read_primary(&self) -> Result<String, Box<dyn Error>> {
-        Ok(self.context.lock().unwrap().load_primary()?)
+    /// Connect to the Wayland compositor named by the environment, like
+    /// [`Clipboard::connect_from_env`], without spawning any threads.
+    pub fn connect_from_env_without_thread() -> Result<Clipboard, Error> {
+        Self::local(Connection::connect_to_env()?)
     }
 
-    pub fn write(&mut self, data: String) -> Result<(), Box<dyn Error>> {
-        self.context.lock().unwrap().store(data);
+    fn local(connection: Connection) -> Result<Clipboard, Error> {
+        let (commands, receiver) = channel::channel();
+        let local =
+            Local::new(connection, receiver).ok_or(Error::WorkerDied)?;
 
-        Ok(())
+        Ok(Clipboard {
+            commands,
+            worker: None,
+            fd: Some(local.fd()?),
+            local: Some(Mutex::new(local)),
+        })
     }
 
-    pub fn write_primary(&mut self, data: String) -> Result<(), Box<dyn Error>> {
-        self.context.lock().unwrap().store_primary(data);
+    /// Connect to the Wayland compositor named by the environment, with a
+    /// connection of our own.
+    ///
+    /// Compositors only let clients with keyboard focus use the selections,
+    /// and this connection has no surfaces of its own; so most operations
+    /// fail with [`Error::NoSeat`] or [`Error::Unfocused`].
+    pub fn connect_from_env() -> Result<Clipboard, Error> {
+        let connection = Connection::connect_to_env()?;
+
+        let (commands, receiver) = channel::channel();
+        let worker = worker::spawn(connection, receiver);
+
+        Ok(Clipboard {
+            commands,
+            worker,
+            local: None,
+            fd: None,
+        })
+    }
+
+    /// Handles the pending events and requests of the clipboard without
+    /// blocking.
+    ///
+    /// It does nothing unless connected without a thread.
+    pub fn dispatch_pending(&self) -> Result<(), Error> {
+        match &self.local {
+            Some(local) => local
+                .lock()
+                .map_err(|_| Error::WorkerDied)?
+                .dispatch(Duration::ZERO),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the file descriptor that becomes readable once
+    /// [`Clipboard::dispatch_pending`] has work to do, unless the clipboard
+    /// runs in a thread of its own.
+    pub fn fd(&self) -> Option<BorrowedFd<'_>> {
+        self.fd.as_ref().map(AsFd::as_fd)
+    }
+
+    /// Returns when [`Clipboard::dispatch_pending`] must be called next, for
+    /// timeouts and expiries, if ever.
+    ///
+    /// It is always `None` if the clipboard runs in a thread of its own.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.local.as_ref()?.lock().ok()?.next_deadline()
+    }
+
+    /// Waits for the given reply of the worker, serving the clipboard in the
+    /// meantime if it has no thread of its own.
+    fn wait<T>(&self, reply: &mpsc::Receiver<T>) -> Result<T, Error> {
+        let Some(local) = &self.local else {
+            return reply.recv().map_err(|_| Error::WorkerDied);
+        };
+
+        loop {
+            match reply.try_recv() {
+                Ok(value) => return Ok(value),
+                Err(mpsc::TryRecvError::Empty) => {}
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    return Err(Error::WorkerDied);
+                }
+            }
+
+            local
+                .lock()
+                .map_err(|_| Error::WorkerDied)?
+                .dispatch(DISPATCH_SLICE)?;
+        }
+    }
+
+    pub fn read(&self) -> Result<String, Error> {
+        self.read_from(Selection::Clipboard)
+    }
+
+    pub fn read_primary(&self) -> Result<String, Error> {
+        self.read_from(Selection::Primary)
+    }
+
+    /// Reads the text contents of the given [`Selection`].
+    pub fn read_from(&self, selection: Selection) -> Result<String, Error> {
+        self.read_with(selection, &ReadOptions::default())
+    }
+
+    /// Reads the text contents of the given [`Selection`] with the given
+    /// [`ReadOptions`].
+    pub fn read_with(
+        &self,
+        selection: Selection,
+        options: &ReadOptions,
+    ) -> Result<String, Error> {
+        let (mime_type, data) = self.load(selection, &mime::TEXT, options)?;
+
+        text(&mime_type, data)
+    }
+
+    /// Reads the text contents of the given [`Selection`] without blocking,
+    /// calling `on_done` once they arrive.
+    ///
+    /// `on_done` is called from the thread serving the clipboard, or from
+    /// [`Clipboard::dispatch_pending`] without one, so it must not wait for
+    /// the clipboard. It is not called if the worker dies during the
+    /// transfer.
+    pub fn read_async(
+        &self,
+        selection: Selection,
+        options: &ReadOptions,
+        on_done: impl FnOnce(Result<String, Error>) + Send + 'static,
+    ) {
+        self.load_async(selection, &mime::TEXT, options, |loaded| {
+            on_done(
+                loaded.and_then(|(mime_type, data)| text(&mime_type, data)),
+            );
+        });
+    }
+
+    /// Reads the contents of the given [`Selection`] in the given mime type
+    /// without blocking, calling `on_done` like [`Clipboard::read_async`].
+    pub fn read_format_async(
+        &self,
+        selection: Selection,
+        mime_type: &str,
+        options: &ReadOptions,
+        on_done: impl FnOnce(Result<Vec<u8>, Error>) + Send + 'static,
+    ) {
+        self.load_async(selection, &[mime_type], options, |loaded| {
+            on_done(loaded.map(|(_, data)| data));
+        });
+    }
+
+    pub fn write(&self, data: String) -> Result<(), Error> {
+        self.write_to(Selection::Clipboard, data)
+    }
+
+    pub fn write_primary(&self, data: String) -> Result<(), Error> {
+        self.write_to(Selection::Primary, data)
+    }
+
+    /// Offers the given text in the given [`Selection`].
+    pub fn write_to(
+        &self,
+        selection: Selection,
+        data: String,
+    ) -> Result<(), Error> {
+        self.store(
+            selection,
+            vec![(mime::TEXT[0], Data::Ready(Arc::from(data.into_bytes())))],
+        )
+    }
+
+    /// Offers the given text in the clipboard until the given time passes.
+    pub fn write_with_expiry(
+        &self,
+        data: String,
+        expiry: Duration,
+    ) -> Result<(), Error> {
+        self.write_with_expiry_to(Selection::Clipboard, data, expiry)
+    }
+
+    /// Offers the given text in the given [`Selection`] until the given time
+    /// passes.
+    ///
+    /// The selection is only cleared if it still holds this text by then.
+    pub fn write_with_expiry_to(
+        &self,
+        selection: Selection,
+        data: String,
+        expiry: Duration,
+    ) -> Result<(), Error> {
+        self.store_until(
+            selection,
+            vec![(mime::TEXT[0], Data::Ready(Arc::from(data.into_bytes())))],
+            Some(expiry),
+        )
+    }
+
+    /// Offers the given secret text in the clipboard, asking clipboard
+    /// managers not to record it.
+    pub fn write_sensitive(&self, data: String) -> Result<(), Error> {
+        self.write_sensitive_to(Selection::Clipboard, data)
+    }
+
+    /// Offers the given secret text in the given [`Selection`], asking
+    /// clipboard managers not to record it.
+    pub fn write_sensitive_to(
+        &self,
+        selection: Selection,
+        data: String,
+    ) -> Result<(), Error> {
+        let text = (mime::TEXT[0], Data::Ready(Arc::from(data.into_bytes())));
+
+        self.store(
+            selection,
+            std::iter::once(text)
+                .chain(mime::SENSITIVE_HINTS.iter().map(|(hint, value)| {
+                    (*hint, Data::Ready(Arc::from(*value)))
+                }))
+                .collect(),
+        )
+    }
+
+    /// Reads the clipboard contents in the given mime type.
+    pub fn read_format(&self, mime_type: &str) -> Result<Vec<u8>, Error> {
+        self.read_format_from(Selection::Clipboard, mime_type)
+    }
+
+    /// Reads the contents of the given [`Selection`] in the given mime type.
+    pub fn read_format_from(
+        &self,
+        selection: Selection,
+        mime_type: &str,
+    ) -> Result<Vec<u8>, Error> {
+        self.read_format_with(selection, mime_type, &ReadOptions::default())
+    }
+
+    /// Reads the contents of the given [`Selection`] in the given mime type
+    /// with the given [`ReadOptions`].
+    pub fn read_format_with(
+        &self,
+        selection: Selection,
+        mime_type: &str,
+        options: &ReadOptions,
+    ) -> Result<Vec<u8>, Error> {
+        let (_, data) = self.load(selection, &[mime_type], options)?;
+
+        Ok(data)
+    }
+
+    /// Streams the contents of the given [`Selection`] in the given mime type
+    /// into `output` as they arrive, returning the bytes written.
+    pub fn read_to(
+        &self,
+        selection: Selection,
+        mime_type: &str,
+        output: &mut dyn Write,
+        options: &ReadOptions,
+    ) -> Result<usize, Error> {
+        let (_, written) =
+            self.load_to(selection, &[mime_type], output, options)?;
+
+        Ok(written)
+    }
+
+    /// Offers the given contents in the clipboard, one entry per mime type.
+    pub fn write_formats(
+        &self,
+        formats: &[(&str, &[u8])],
+    ) -> Result<(), Error> {
+        self.write_formats_to(Selection::Clipboard, formats)
+    }
+
+    /// Offers the given contents in the given [`Selection`], one entry per
+    /// mime type.
+    pub fn write_formats_to(
+        &self,
+        selection: Selection,
+        formats: &[(&str, &[u8])],
+    ) -> Result<(), Error> {
+        self.store(
+            selection,
+            formats
+                .iter()
+                .map(|(mime_type, data)| {
+                    (*mime_type, Data::Ready(Arc::from(*data)))
+                })
+                .collect(),
+        )
+    }
+
+    /// Offers the clipboard in the given mime types, without producing any
+    /// contents until a client asks for one of them.
+    pub fn write_lazy(
+        &self,
+        mime_types: &[&str],
+        provider: Provider,
+    ) -> Result<(), Error> {
+        self.write_lazy_to(Selection::Clipboard, mime_types, provider)
+    }
+
+    /// Offers the given [`Selection`] in the given mime types, calling the
+    /// [`Provider`] from the thread serving the clipboard whenever a client
+    /// asks for one of them.
+    pub fn write_lazy_to(
+        &self,
+        selection: Selection,
+        mime_types: &[&str],
+        provider: Provider,
+    ) -> Result<(), Error> {
+        self.store(
+            selection,
+            mime_types
+                .iter()
+                .map(|mime_type| {
+                    let data = Data::Lazy {
+                        mime_type: String::from(*mime_type),
+                        provider: Arc::clone(&provider),
+                    };
+
+                    (*mime_type, data)
+                })
+                .collect(),
+        )
+    }
+
+    /// Offers the clipboard in the given mime type, reading the contents from
+    /// the [`Source`] only when a client asks for them.
+    pub fn write_from_reader(
+        &self,
+        mime_type: &str,
+        source: Source,
+    ) -> Result<(), Error> {
+        self.write_from_reader_to(Selection::Clipboard, mime_type, source)
+    }
+
+    /// Offers the given [`Selection`] in the given mime type, streaming the
+    /// contents from the [`Source`] to every client asking for them.
+    pub fn write_from_reader_to(
+        &self,
+        selection: Selection,
+        mime_type: &str,
+        source: Source,
+    ) -> Result<(), Error> {
+        self.store(selection, vec![(mime_type, Data::Stream(source))])
+    }
+
+    /// Returns the mime types offered by the current clipboard owner.
+    pub fn available_formats(&self) -> Result<Vec<String>, Error> {
+        self.available_formats_in(Selection::Clipboard)
+    }
+
+    /// Returns the mime types offered by the current owner of the given
+    /// [`Selection`].
+    pub fn available_formats_in(
+        &self,
+        selection: Selection,
+    ) -> Result<Vec<String>, Error> {
+        let (reply, response) = mpsc::channel();
+
+        self.available_formats_async(selection, move |formats| {
+            let _ = reply.send(formats);
+        });
+
+        self.wait(&response)?
+    }
+
+    /// Lists the mime types offered by the current owner of the given
+    /// [`Selection`] without blocking, calling `on_done` like
+    /// [`Clipboard::read_async`].
+    pub fn available_formats_async(
+        &self,
+        selection: Selection,
+        on_done: impl FnOnce(Result<Vec<String>, Error>) + Send + 'static,
+    ) {
+        let command = Command::Formats {
+            selection,
+            on_done: Box::new(on_done),
+        };
+
+        if let Err(mpsc::SendError(Command::Formats { on_done, .. })) =
+            self.commands.send(command)
+        {
+            on_done(Err(Error::WorkerDied));
+        }
+    }
+
+    /// Returns whether the compositor supports the given [`Selection`].
+    pub fn supports(&self, selection: Selection) -> Result<bool, Error> {
+        let (reply, response) = mpsc::channel();
+
+        self.commands
+            .send(Command::Supports { selection, reply })
+            .map_err(|_| Error::WorkerDied)?;
+
+        self.wait(&response)
+    }
+
+    /// Calls `on_change` with the [`Selection`] that changed, every time it
+    /// happens, for as long as it returns `true`.
+    ///
+    /// The callback runs in the thread serving the clipboard, so it should
+    /// return quickly. Compositors only report changes while one of our
+    /// surfaces has keyboard focus.
+    pub fn watch(
+        &self,
+        on_change: impl FnMut(Selection) -> bool + Send + 'static,
+    ) -> Result<(), Error> {
+        self.commands
+            .send(Command::Watch(Box::new(on_change)))
+            .map_err(|_| Error::WorkerDied)
+    }
+
+    fn load(
+        &self,
+        selection: Selection,
+        mime_types: &[&str],
+        options: &ReadOptions,
+    ) -> Result<(String, Vec<u8>), Error> {
+        let mut data = Vec::new();
+
+        let (mime_type, _) =
+            self.load_to(selection, mime_types, &mut data, options)?;
+
+        Ok((mime_type, data))
+    }
+
+    fn load_to(
+        &self,
+        selection: Selection,
+        mime_types: &[&str],
+        output: &mut dyn Write,
+        options: &ReadOptions,
+    ) -> Result<(String, usize), Error> {
+        let (sender, transfers) = mpsc::channel();
+
+        self.submit(
+            selection,
+            mime_types,
+            options,
+            Box::new(move |transfer| sender.send(transfer).is_ok()),
+        );
+
+        let mut mime_type = String::new();
+        let mut written = 0;
+
+        // The worker ends the transfer once it times out or is cancelled
+        loop {
+            match self.wait(&transfers)?? {
+                Transfer::Started(chosen) => mime_type = chosen,
+                Transfer::Data(data) => {
+                    written += data.len();
+
+                    if options
+                        .max_bytes
+                        .is_some_and(|max_bytes| written > max_bytes)
+                    {
+                        return Err(Error::TooLarge);
+                    }
+
+                    output.write_all(&data)?;
+                }
+                Transfer::Finished => return Ok((mime_type, written)),
+            }
+        }
+    }
+
+    /// Loads the given selection without blocking, calling `on_done` with
+    /// the chosen mime type and the contents once they arrive.
+    fn load_async(
+        &self,
+        selection: Selection,
+        mime_types: &[&str],
+        options: &ReadOptions,
+        on_done: impl FnOnce(Result<(String, Vec<u8>), Error>) + Send + 'static,
+    ) {
+        let max_bytes = options.max_bytes;
+        let mut on_done = Some(on_done);
+        let mut mime_type = String::new();
+        let mut contents = Vec::new();
+
+        let sink = Box::new(move |transfer| {
+            let result = match transfer {
+                Ok(Transfer::Started(chosen)) => {
+                    mime_type = chosen;
+                    return true;
+                }
+                Ok(Transfer::Data(data)) => {
+                    if max_bytes.is_some_and(|max_bytes| {
+                        contents.len() + data.len() > max_bytes
+                    }) {
+                        Err(Error::TooLarge)
+                    } else {
+                        contents.extend_from_slice(&data);
+                        return true;
+                    }
+                }
+                Ok(Transfer::Finished) => Ok((
+                    std::mem::take(&mut mime_type),
+                    std::mem::take(&mut contents),
+                )),
+                Err(error) => Err(error),
+            };
+
+            if let Some(on_done) = on_done.take() {
+                on_done(result);
+            }
+
+            false
+        });
+
+        self.submit(selection, mime_types, options, sink);
+    }
+
+    /// Asks the worker to load the given selection in the first offered mime
+    /// type, handing the transfer to `sink`.
+    fn submit(
+        &self,
+        selection: Selection,
+        mime_types: &[&str],
+        options: &ReadOptions,
+        sink: Sink,
+    ) {
+        let mime_types = mime::expand(mime_types.iter().copied())
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        let command = Command::Load {
+            selection,
+            mime_types,
+            options: options.clone(),
+            sink,
+        };
+
+        if let Err(mpsc::SendError(Command::Load { mut sink, .. })) =
+            self.commands.send(command)
+        {
+            let _ = sink(Err(Error::WorkerDied));
+        }
+    }
+
+    fn store(
+        &self,
+        selection: Selection,
+        contents: Vec<(&str, Data)>,
+    ) -> Result<(), Error> {
+        self.store_until(selection, contents, None)
+    }
+
+    fn store_until(
+        &self,
+        selection: Selection,
+        contents: Vec<(&str, Data)>,
+        expiry: Option<Duration>,
+    ) -> Result<(), Error> {
+        let mut expanded = Vec::new();
+
+        for (mime_type, data) in contents {
+            for alias in mime::expand([mime_type]) {
+                if !expanded.iter().any(|(known, _)| known == alias) {
+                    expanded.push((alias.to_owned(), data.clone()));
+                }
+            }
+        }
+
+        let (reply, response) = mpsc::channel();
+
+        self.commands
+            .send(Command::Store {
+                selection,
+                contents: expanded,
+                expiry,
+                reply,
+            })
+            .map_err(|_| Error::WorkerDied)?;
+
+        self.wait(&response)?
+    }
+}
+
+/// Decodes text received in the given mime type, with the line endings of
+/// plain text normalized.
+fn text(mime_type: &str, data: Vec<u8>) -> Result<String, Error> {
+    let text = String::from_utf8(data).map_err(Error::InvalidUtf8)?;
+
+    if mime_type.starts_with("text/plain") {
+        Ok(mime::normalize_to_lf(text))
+    } else {
+        Ok(text)
+    }
+}
+
+impl Drop for Clipboard {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Exit);
 
-        Ok(())
+        // The last handle may be dropped by a callback run on the worker,
+        // which exits on its own once it returns
+        if let Some(worker) = self.worker.take() {
+            if worker.thread().id() != thread::current().id() {
+                let _ = worker.join();
+            }
+        }
     }
 }
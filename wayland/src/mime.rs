@@ -0,0 +1,48 @@
+/// The mime types offered and accepted for plain text, by preference.
+///
+/// `UTF8_STRING` is kept for X11 clients running through Xwayland, which
+/// may only know about that target.
+pub const TEXT: [&str; 3] =
+    ["text/plain;charset=utf-8", "UTF8_STRING", "text/plain"];
+
+/// The mime types offered along with secrets, with the value clipboard
+/// managers look for before skipping them.
+pub const SENSITIVE_HINTS: [(&str, &[u8]); 1] =
+    [("x-kde-passwordManagerHint", b"secret")];
+
+/// Returns the mime types that carry the same data as the given one.
+pub fn aliases(mime_type: &str) -> &'static [&'static str] {
+    if TEXT.contains(&mime_type) {
+        &TEXT
+    } else {
+        &[]
+    }
+}
+
+/// Expands the given mime types with their aliases, keeping their order and
+/// dropping duplicates.
+pub fn expand<'a>(
+    mime_types: impl IntoIterator<Item = &'a str>,
+) -> Vec<&'a str> {
+    let mut expanded = Vec::new();
+
+    for mime_type in mime_types {
+        for candidate in
+            std::iter::once(mime_type).chain(aliases(mime_type).iter().copied())
+        {
+            if !expanded.contains(&candidate) {
+                expanded.push(candidate);
+            }
+        }
+    }
+
+    expanded
+}
+
+/// Normalize CR and CRLF into LF.
+///
+/// `text` mime types require CRLF line endings according to RFC 2046;
+/// however, applications expect the platform line terminator.
+pub fn normalize_to_lf(text: String) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
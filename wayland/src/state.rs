@@ -0,0 +1,996 @@
+// `ObjectId` is hashed by its protocol id, which never changes.
+#![allow(clippy::mutable_key_type)]
+
+use crate::{Error, Provider, ReadOptions, Selection, Source};
+
+use sctk::data_device_manager::data_device::{DataDevice, DataDeviceHandler};
+use sctk::data_device_manager::data_offer::{
+    DataOfferError, DataOfferHandler, DragOffer,
+};
+use sctk::data_device_manager::data_source::{
+    CopyPasteSource, DataSourceHandler,
+};
+use sctk::data_device_manager::{DataDeviceManagerState, ReadPipe, WritePipe};
+use sctk::primary_selection::device::{
+    PrimarySelectionDevice, PrimarySelectionDeviceHandler,
+};
+use sctk::primary_selection::selection::{
+    PrimarySelectionSource, PrimarySelectionSourceHandler,
+};
+use sctk::primary_selection::PrimarySelectionManagerState;
+use sctk::reexports::calloop::timer::{TimeoutAction, Timer};
+use sctk::reexports::calloop::{LoopHandle, PostAction};
+use sctk::reexports::client::globals::GlobalList;
+use sctk::reexports::client::protocol::wl_data_device::WlDataDevice;
+use sctk::reexports::client::protocol::wl_data_device_manager::DndAction;
+use sctk::reexports::client::protocol::wl_data_source::WlDataSource;
+use sctk::reexports::client::protocol::wl_keyboard::{self, WlKeyboard};
+use sctk::reexports::client::protocol::wl_pointer::WlPointer;
+use sctk::reexports::client::protocol::wl_seat::WlSeat;
+use sctk::reexports::client::protocol::wl_surface::WlSurface;
+use sctk::reexports::client::{Connection, Dispatch, Proxy, QueueHandle};
+use sctk::reexports::protocols::wp::primary_selection::zv1::client::{
+    zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1,
+    zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1,
+};
+use sctk::registry::{ProvidesRegistryState, RegistryState};
+use sctk::seat::pointer::{
+    PointerData, PointerEvent, PointerEventKind, PointerHandler,
+};
+use sctk::seat::{Capability, SeatHandler, SeatState};
+use sctk::{
+    delegate_data_device, delegate_pointer, delegate_primary_selection,
+    delegate_registry, delegate_seat, registry_handlers,
+};
+use wayland_backend::client::ObjectId;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often a pending read checks whether it was cancelled.
+const CANCEL_POLL: Duration = Duration::from_millis(10);
+
+/// The contents offered for a selection, indexed by mime type.
+pub type Contents = HashMap<String, Data>;
+
+/// The contents offered in a single mime type.
+#[derive(Clone)]
+pub enum Data {
+    Ready(Arc<[u8]>),
+    /// Contents produced on demand in the given mime type.
+    Lazy {
+        mime_type: String,
+        provider: Provider,
+    },
+    /// Contents read from a new reader for every request.
+    Stream(Source),
+}
+
+/// Receives the messages of a selection being loaded, returning `false` once
+/// it does not want any more.
+pub type Sink = Box<dyn FnMut(Result<Transfer, Error>) -> bool + Send>;
+
+/// A message of a selection being loaded.
+pub enum Transfer {
+    /// The data is sent in the given mime type.
+    Started(String),
+    /// A chunk of the data.
+    Data(Vec<u8>),
+    /// All the data was sent.
+    Finished,
+}
+
+pub struct State {
+    pub exit: bool,
+    /// When the timers of the event loop fire, which its file descriptor
+    /// does not report.
+    pub deadlines: Vec<Instant>,
+
+    registry_state: RegistryState,
+    seat_state: SeatState,
+    data_device_manager_state: Option<DataDeviceManagerState>,
+    primary_selection_manager_state: Option<PrimarySelectionManagerState>,
+
+    seats: HashMap<ObjectId, Seat>,
+    /// The latest seat which got an input event.
+    latest_seat: Option<ObjectId>,
+
+    loop_handle: LoopHandle<'static, Self>,
+    queue_handle: QueueHandle<Self>,
+
+    data_sources: Vec<CopyPasteSource>,
+    data_contents: Contents,
+
+    primary_sources: Vec<PrimarySelectionSource>,
+    primary_contents: Contents,
+
+    watchers: Vec<Box<dyn FnMut(Selection) -> bool + Send>>,
+}
+
+impl State {
+    pub fn new(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<Self>,
+        loop_handle: LoopHandle<'static, Self>,
+    ) -> Option<Self> {
+        let data_device_manager_state =
+            DataDeviceManagerState::bind(globals, queue_handle).ok();
+        let primary_selection_manager_state =
+            PrimarySelectionManagerState::bind(globals, queue_handle).ok();
+
+        // Nothing can be done without any of the selection globals
+        if data_device_manager_state.is_none()
+            && primary_selection_manager_state.is_none()
+        {
+            return None;
+        }
+
+        let seat_state = SeatState::new(globals, queue_handle);
+        let seats = seat_state
+            .seats()
+            .map(|seat| (seat.id(), Seat::default()))
+            .collect();
+
+        Some(Self {
+            exit: false,
+            deadlines: Vec::new(),
+            registry_state: RegistryState::new(globals),
+            seat_state,
+            data_device_manager_state,
+            primary_selection_manager_state,
+            seats,
+            latest_seat: None,
+            loop_handle,
+            queue_handle: queue_handle.clone(),
+            data_sources: Vec::new(),
+            data_contents: Contents::new(),
+            primary_sources: Vec::new(),
+            primary_contents: Contents::new(),
+            watchers: Vec::new(),
+        })
+    }
+
+    /// Offers the given contents in the given selection.
+    pub fn store(
+        &mut self,
+        selection: Selection,
+        contents: Contents,
+    ) -> Result<(), Error> {
+        let seat = focused_seat(&self.seats, self.latest_seat.as_ref())?;
+        let mime_types: Vec<String> = contents.keys().cloned().collect();
+
+        match selection {
+            Selection::Clipboard => {
+                let (manager, device) = self
+                    .data_device_manager_state
+                    .as_ref()
+                    .zip(seat.data_device.as_ref())
+                    .ok_or(Error::UnsupportedSelection)?;
+
+                let source = manager
+                    .create_copy_paste_source(&self.queue_handle, mime_types);
+
+                source.set_selection(device, seat.latest_serial);
+
+                self.data_sources.push(source);
+                self.data_contents = contents;
+            }
+            Selection::Primary => {
+                let (manager, device) = self
+                    .primary_selection_manager_state
+                    .as_ref()
+                    .zip(seat.primary_device.as_ref())
+                    .ok_or(Error::UnsupportedSelection)?;
+
+                let source = manager
+                    .create_selection_source(&self.queue_handle, mime_types);
+
+                source.set_selection(device, seat.latest_serial);
+
+                self.primary_sources.push(source);
+                self.primary_contents = contents;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Withdraws the current contents of the given selection after the given
+    /// time, unless they were replaced in the meantime.
+    pub fn expire(&mut self, selection: Selection, expiry: Duration) {
+        let source = match selection {
+            Selection::Clipboard => {
+                self.data_sources.last().map(|source| source.inner().id())
+            }
+            Selection::Primary => self
+                .primary_sources
+                .last()
+                .map(|source| source.inner().id()),
+        };
+
+        // Expiries too far away to represent never pass
+        let (Some(source), Some(deadline)) =
+            (source, Instant::now().checked_add(expiry))
+        else {
+            return;
+        };
+
+        self.deadlines.push(deadline);
+
+        let _ = self.loop_handle.insert_source(
+            Timer::from_deadline(deadline),
+            move |_, _, state| {
+                state.unschedule(deadline);
+                state.withdraw(selection, &source);
+
+                TimeoutAction::Drop
+            },
+        );
+    }
+
+    /// Forgets a timer firing at the given time.
+    fn unschedule(&mut self, deadline: Instant) {
+        if let Some(index) =
+            self.deadlines.iter().position(|known| *known == deadline)
+        {
+            let _ = self.deadlines.swap_remove(index);
+        }
+    }
+
+    /// Destroys the given source if it still holds the selection, which
+    /// clears it.
+    fn withdraw(&mut self, selection: Selection, source: &ObjectId) {
+        // Cancelled sources are forgotten, so the source is only the latest
+        // one while we own the selection
+        match selection {
+            Selection::Clipboard => {
+                if self
+                    .data_sources
+                    .last()
+                    .is_some_and(|current| current.inner().id() == *source)
+                {
+                    let _ = self.data_sources.pop();
+                    self.data_contents.clear();
+                }
+            }
+            Selection::Primary => {
+                if self
+                    .primary_sources
+                    .last()
+                    .is_some_and(|current| current.inner().id() == *source)
+                {
+                    let _ = self.primary_sources.pop();
+                    self.primary_contents.clear();
+                }
+            }
+        }
+    }
+
+    /// Returns whether the compositor supports the given selection.
+    pub fn supports(&self, selection: Selection) -> bool {
+        match selection {
+            Selection::Clipboard => self.data_device_manager_state.is_some(),
+            Selection::Primary => {
+                self.primary_selection_manager_state.is_some()
+            }
+        }
+    }
+
+    /// Calls `on_change` on every selection change, for as long as it
+    /// returns `true`.
+    pub fn watch(
+        &mut self,
+        on_change: Box<dyn FnMut(Selection) -> bool + Send>,
+    ) {
+        self.watchers.push(on_change);
+    }
+
+    fn notify(&mut self, selection: Selection) {
+        self.watchers.retain_mut(|on_change| on_change(selection));
+    }
+
+    /// Returns the mime types offered in the given selection.
+    pub fn formats(&self, selection: Selection) -> Result<Vec<String>, Error> {
+        let seat = focused_seat(&self.seats, self.latest_seat.as_ref())?;
+
+        let mime_types = match selection {
+            Selection::Clipboard => seat
+                .data_device
+                .as_ref()
+                .ok_or(Error::UnsupportedSelection)?
+                .data()
+                .selection_offer()
+                .map(|offer| offer.with_mime_types(<[String]>::to_vec)),
+            Selection::Primary => seat
+                .primary_device
+                .as_ref()
+                .ok_or(Error::UnsupportedSelection)?
+                .data()
+                .selection_offer()
+                .map(|offer| offer.with_mime_types(<[String]>::to_vec)),
+        };
+
+        Ok(mime_types.unwrap_or_default())
+    }
+
+    /// Loads the given selection in the first offered mime type, handing
+    /// every message of the transfer to `sink` until it returns `false`.
+    ///
+    /// The transfer fails once cancelled, or once its timeout passes.
+    pub fn load(
+        &mut self,
+        selection: Selection,
+        mime_types: &[String],
+        options: ReadOptions,
+        mut sink: Sink,
+    ) {
+        let (pipe, mime_type) = match self.open(selection, mime_types) {
+            Ok(opened) => opened,
+            Err(error) => {
+                let _ = sink(Err(error));
+                return;
+            }
+        };
+
+        if !sink(Ok(Transfer::Started(mime_type))) {
+            return;
+        }
+
+        // Both the pipe and the timer may end the transfer, so the sink goes
+        // away with the first of them
+        let sink = Rc::new(RefCell::new(Some(sink)));
+        let reading = Rc::clone(&sink);
+        let mut buffer = [0; 4096];
+
+        let inserted =
+            self.loop_handle.insert_source(pipe, move |_, file, _| {
+                // SAFETY: the pipe is never dropped by us while the source
+                // is registered.
+                let file = unsafe { file.get_mut() };
+                let mut reading = reading.borrow_mut();
+
+                loop {
+                    let Some(sink) = reading.as_mut() else {
+                        break PostAction::Remove;
+                    };
+
+                    let message = match file.read(&mut buffer) {
+                        Ok(0) => Ok(Transfer::Finished),
+                        Ok(n) => Ok(Transfer::Data(buffer[..n].to_vec())),
+                        Err(error)
+                            if error.kind() == io::ErrorKind::WouldBlock =>
+                        {
+                            break PostAction::Continue;
+                        }
+                        Err(error) => Err(Error::Io(error)),
+                    };
+
+                    let finished = !matches!(message, Ok(Transfer::Data(_)));
+
+                    // The reader may also give up
+                    if !sink(message) || finished {
+                        *reading = None;
+                    }
+                }
+            });
+
+        let pipe = match inserted {
+            Ok(token) => token,
+            Err(error) => {
+                if let Some(mut sink) = sink.borrow_mut().take() {
+                    let _ = sink(Err(Error::Io(io::Error::other(error.error))));
+                }
+
+                return;
+            }
+        };
+
+        let deadline = options
+            .timeout
+            .and_then(|timeout| Instant::now().checked_add(timeout));
+
+        let Some(check) = next_check(deadline, &options) else {
+            return;
+        };
+
+        let mut scheduled = check;
+        self.deadlines.push(scheduled);
+
+        let _ = self.loop_handle.insert_source(
+            Timer::from_deadline(check),
+            move |_, _, state| {
+                state.unschedule(scheduled);
+
+                let mut sink = sink.borrow_mut();
+
+                let Some(reading) = sink.as_mut() else {
+                    return TimeoutAction::Drop;
+                };
+
+                let now = Instant::now();
+
+                let error = if options.is_cancelled() {
+                    Error::Cancelled
+                } else if deadline.is_some_and(|deadline| deadline <= now) {
+                    Error::Timeout
+                } else {
+                    let Some(check) = next_check(deadline, &options) else {
+                        return TimeoutAction::Drop;
+                    };
+
+                    scheduled = check;
+                    state.deadlines.push(scheduled);
+
+                    return TimeoutAction::ToInstant(check);
+                };
+
+                let _ = reading(Err(error));
+                *sink = None;
+                state.loop_handle.remove(pipe);
+
+                TimeoutAction::Drop
+            },
+        );
+    }
+
+    /// Asks the owner of the given selection for its contents in the first
+    /// offered mime type, returning the pipe they are sent through.
+    fn open(
+        &self,
+        selection: Selection,
+        mime_types: &[String],
+    ) -> Result<(ReadPipe, String), Error> {
+        let seat = focused_seat(&self.seats, self.latest_seat.as_ref())?;
+
+        let (pipe, mime_type) = match selection {
+            Selection::Clipboard => {
+                let offer = seat
+                    .data_device
+                    .as_ref()
+                    .ok_or(Error::UnsupportedSelection)?
+                    .data()
+                    .selection_offer()
+                    .ok_or(Error::EmptySelection)?;
+
+                let mime_type = offer
+                    .with_mime_types(|offered| choose(offered, mime_types))
+                    .ok_or(Error::UnsupportedMimeType)?;
+
+                let pipe =
+                    offer.receive(mime_type.clone()).map_err(|error| {
+                        match error {
+                            DataOfferError::InvalidReceive => Error::Io(
+                                io::Error::other("offer is not ready"),
+                            ),
+                            DataOfferError::Io(error) => Error::Io(error),
+                        }
+                    })?;
+
+                (pipe, mime_type)
+            }
+            Selection::Primary => {
+                let offer = seat
+                    .primary_device
+                    .as_ref()
+                    .ok_or(Error::UnsupportedSelection)?
+                    .data()
+                    .selection_offer()
+                    .ok_or(Error::EmptySelection)?;
+
+                let mime_type = offer
+                    .with_mime_types(|offered| choose(offered, mime_types))
+                    .ok_or(Error::UnsupportedMimeType)?;
+
+                (offer.receive(mime_type.clone())?, mime_type)
+            }
+        };
+
+        set_non_blocking(pipe.as_raw_fd())?;
+
+        Ok((pipe, mime_type))
+    }
+
+    fn send(
+        &mut self,
+        selection: Selection,
+        mime_type: String,
+        pipe: WritePipe,
+    ) {
+        // Keep our own reference, since the contents may be replaced during
+        // the transfer
+        let contents = match selection {
+            Selection::Clipboard => self.data_contents.get(&mime_type),
+            Selection::Primary => self.primary_contents.get(&mime_type),
+        };
+
+        let mut reader: Box<dyn Read + Send> = match contents.cloned() {
+            Some(Data::Ready(contents)) => Box::new(io::Cursor::new(contents)),
+            Some(Data::Lazy {
+                mime_type,
+                provider,
+            }) => match provider(&mime_type) {
+                Some(contents) => Box::new(io::Cursor::new(contents)),
+                None => return,
+            },
+            Some(Data::Stream(source)) => match source() {
+                Ok(reader) => reader,
+                Err(_) => return,
+            },
+            None => return,
+        };
+
+        if set_non_blocking(pipe.as_raw_fd()).is_err() {
+            return;
+        }
+
+        let mut chunk = vec![0; 4096];
+        let mut pending = 0..0;
+
+        let _ = self.loop_handle.insert_source(pipe, move |_, file, _| {
+            // SAFETY: the pipe is never dropped by us while the source
+            // is registered.
+            let file = unsafe { file.get_mut() };
+
+            loop {
+                // There is no way to report a failure midway, so the
+                // transfer just ends early
+                if pending.is_empty() {
+                    match reader.read(&mut chunk) {
+                        Ok(0) => break PostAction::Remove,
+                        Ok(n) => pending = 0..n,
+                        Err(error)
+                            if error.kind() == io::ErrorKind::Interrupted =>
+                        {
+                            continue;
+                        }
+                        Err(_) => break PostAction::Remove,
+                    }
+                }
+
+                match file.write(&chunk[pending.clone()]) {
+                    Ok(n) => pending.start += n,
+                    Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                        break PostAction::Continue;
+                    }
+                    Err(error)
+                        if error.kind() == io::ErrorKind::Interrupted => {}
+                    Err(_) => break PostAction::Remove,
+                }
+            }
+        });
+    }
+}
+
+fn focused_seat<'a>(
+    seats: &'a HashMap<ObjectId, Seat>,
+    latest: Option<&ObjectId>,
+) -> Result<&'a Seat, Error> {
+    let seat = latest
+        .and_then(|latest| seats.get(latest))
+        .ok_or(Error::NoSeat)?;
+
+    if seat.has_focus {
+        Ok(seat)
+    } else {
+        Err(Error::Unfocused)
+    }
+}
+
+fn choose(offered: &[String], accepted: &[String]) -> Option<String> {
+    accepted
+        .iter()
+        .find(|mime_type| offered.contains(mime_type))
+        .cloned()
+}
+
+/// Returns when a transfer should next look at its deadline and at its
+/// cancellation, if ever.
+fn next_check(
+    deadline: Option<Instant>,
+    options: &ReadOptions,
+) -> Option<Instant> {
+    let poll = options
+        .cancel
+        .as_ref()
+        .map(|_| Instant::now() + CANCEL_POLL);
+
+    match (poll, deadline) {
+        (Some(poll), Some(deadline)) => Some(poll.min(deadline)),
+        (poll, deadline) => poll.or(deadline),
+    }
+}
+
+fn set_non_blocking(raw_fd: RawFd) -> io::Result<()> {
+    // SAFETY: the file descriptor is owned by a live pipe.
+    let flags = unsafe { libc::fcntl(raw_fd, libc::F_GETFL) };
+
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: same as above.
+    let result =
+        unsafe { libc::fcntl(raw_fd, libc::F_SETFL, flags | libc::O_NONBLOCK) };
+
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct Seat {
+    keyboard: Option<WlKeyboard>,
+    pointer: Option<WlPointer>,
+    data_device: Option<DataDevice>,
+    primary_device: Option<PrimarySelectionDevice>,
+    has_focus: bool,
+    /// The latest serial, needed to set the selection.
+    latest_serial: u32,
+}
+
+impl Drop for Seat {
+    fn drop(&mut self) {
+        if let Some(keyboard) = self.keyboard.take() {
+            if keyboard.version() >= 3 {
+                keyboard.release();
+            }
+        }
+
+        if let Some(pointer) = self.pointer.take() {
+            if pointer.version() >= 3 {
+                pointer.release();
+            }
+        }
+    }
+}
+
+impl SeatHandler for State {
+    fn seat_state(&mut self) -> &mut SeatState {
+        &mut self.seat_state
+    }
+
+    fn new_seat(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        seat: WlSeat,
+    ) {
+        let _ = self.seats.insert(seat.id(), Seat::default());
+    }
+
+    fn new_capability(
+        &mut self,
+        _: &Connection,
+        queue_handle: &QueueHandle<Self>,
+        seat: WlSeat,
+        capability: Capability,
+    ) {
+        let state = self.seats.entry(seat.id()).or_default();
+
+        match capability {
+            Capability::Keyboard => {
+                state.keyboard =
+                    Some(seat.get_keyboard(queue_handle, seat.id()));
+
+                // Selections are tied to the keyboard focus, so we create the
+                // devices alongside it
+                if state.data_device.is_none() {
+                    state.data_device = self
+                        .data_device_manager_state
+                        .as_ref()
+                        .map(|manager| {
+                            manager.get_data_device(queue_handle, &seat)
+                        });
+                }
+
+                if state.primary_device.is_none() {
+                    state.primary_device = self
+                        .primary_selection_manager_state
+                        .as_ref()
+                        .map(|manager| {
+                            manager.get_selection_device(queue_handle, &seat)
+                        });
+                }
+            }
+            Capability::Pointer => {
+                state.pointer =
+                    self.seat_state.get_pointer(queue_handle, &seat).ok();
+            }
+            _ => {}
+        }
+    }
+
+    fn remove_capability(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        seat: WlSeat,
+        capability: Capability,
+    ) {
+        let state = match self.seats.get_mut(&seat.id()) {
+            Some(state) => state,
+            None => return,
+        };
+
+        match capability {
+            Capability::Keyboard => {
+                state.data_device = None;
+                state.primary_device = None;
+
+                if let Some(keyboard) = state.keyboard.take() {
+                    if keyboard.version() >= 3 {
+                        keyboard.release();
+                    }
+                }
+            }
+            Capability::Pointer => {
+                if let Some(pointer) = state.pointer.take() {
+                    if pointer.version() >= 3 {
+                        pointer.release();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn remove_seat(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        seat: WlSeat,
+    ) {
+        let _ = self.seats.remove(&seat.id());
+    }
+}
+
+impl PointerHandler for State {
+    fn pointer_frame(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        pointer: &WlPointer,
+        events: &[PointerEvent],
+    ) {
+        let seat = match pointer.data::<PointerData>() {
+            Some(data) => data.seat().id(),
+            None => return,
+        };
+
+        let state = match self.seats.get_mut(&seat) {
+            Some(state) => state,
+            None => return,
+        };
+
+        let mut serial = None;
+
+        for event in events {
+            match event.kind {
+                PointerEventKind::Press { serial: new, .. }
+                | PointerEventKind::Release { serial: new, .. } => {
+                    serial = Some(new);
+                }
+                _ => {}
+            }
+        }
+
+        // Only switch seats when the serial was updated
+        if let Some(serial) = serial {
+            state.latest_serial = serial;
+            self.latest_seat = Some(seat);
+        }
+    }
+}
+
+impl Dispatch<WlKeyboard, ObjectId, State> for State {
+    fn event(
+        state: &mut State,
+        _: &WlKeyboard,
+        event: wl_keyboard::Event,
+        seat: &ObjectId,
+        _: &Connection,
+        _: &QueueHandle<State>,
+    ) {
+        let seat_state = match state.seats.get_mut(seat) {
+            Some(seat_state) => seat_state,
+            None => return,
+        };
+
+        match event {
+            wl_keyboard::Event::Key { serial, .. }
+            | wl_keyboard::Event::Modifiers { serial, .. } => {
+                seat_state.latest_serial = serial;
+                state.latest_seat = Some(seat.clone());
+            }
+            wl_keyboard::Event::Enter { serial, .. } => {
+                seat_state.latest_serial = serial;
+                seat_state.has_focus = true;
+            }
+            wl_keyboard::Event::Leave { .. } => {
+                seat_state.latest_serial = 0;
+                seat_state.has_focus = false;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl DataDeviceHandler for State {
+    fn enter(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataDevice,
+        _: f64,
+        _: f64,
+        _: &WlSurface,
+    ) {
+    }
+
+    fn leave(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataDevice,
+    ) {
+    }
+
+    fn motion(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataDevice,
+        _: f64,
+        _: f64,
+    ) {
+    }
+
+    fn selection(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataDevice,
+    ) {
+        self.notify(Selection::Clipboard);
+    }
+
+    fn drop_performed(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataDevice,
+    ) {
+    }
+}
+
+impl DataOfferHandler for State {
+    fn source_actions(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &mut DragOffer,
+        _: DndAction,
+    ) {
+    }
+
+    fn selected_action(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &mut DragOffer,
+        _: DndAction,
+    ) {
+    }
+}
+
+impl DataSourceHandler for State {
+    fn accept_mime(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataSource,
+        _: Option<String>,
+    ) {
+    }
+
+    fn send_request(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataSource,
+        mime_type: String,
+        pipe: WritePipe,
+    ) {
+        self.send(Selection::Clipboard, mime_type, pipe);
+    }
+
+    fn cancelled(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        source: &WlDataSource,
+    ) {
+        self.data_sources
+            .retain(|current| current.inner() != source);
+    }
+
+    fn dnd_dropped(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataSource,
+    ) {
+    }
+
+    fn dnd_finished(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataSource,
+    ) {
+    }
+
+    fn action(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataSource,
+        _: DndAction,
+    ) {
+    }
+}
+
+impl PrimarySelectionDeviceHandler for State {
+    fn selection(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &ZwpPrimarySelectionDeviceV1,
+    ) {
+        self.notify(Selection::Primary);
+    }
+}
+
+impl PrimarySelectionSourceHandler for State {
+    fn send_request(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &ZwpPrimarySelectionSourceV1,
+        mime_type: String,
+        pipe: WritePipe,
+    ) {
+        self.send(Selection::Primary, mime_type, pipe);
+    }
+
+    fn cancelled(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        source: &ZwpPrimarySelectionSourceV1,
+    ) {
+        self.primary_sources
+            .retain(|current| current.inner() != source);
+    }
+}
+
+impl ProvidesRegistryState for State {
+    registry_handlers![SeatState];
+
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry_state
+    }
+}
+
+delegate_seat!(State);
+delegate_pointer!(State);
+delegate_data_device!(State);
+delegate_primary_selection!(State);
+delegate_registry!(State);
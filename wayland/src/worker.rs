@@ -0,0 +1,179 @@
+use crate::state::{Contents, Data, Sink, State};
+use crate::{Error, ReadOptions, Selection};
+
+use sctk::reexports::calloop::channel::{self, Channel};
+use sctk::reexports::calloop::EventLoop;
+use sctk::reexports::calloop_wayland_source::WaylandSource;
+use sctk::reexports::client::globals::registry_queue_init;
+use sctk::reexports::client::Connection;
+
+use std::io;
+use std::os::unix::io::{AsFd, OwnedFd};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A request handled by the clipboard worker.
+pub enum Command {
+    /// Offer the given contents in a selection.
+    Store {
+        selection: Selection,
+        contents: Vec<(String, Data)>,
+        /// Withdraw the contents after this long, if still offered.
+        expiry: Option<Duration>,
+        reply: Sender<Result<(), Error>>,
+    },
+    /// Load a selection in the first offered mime type.
+    Load {
+        selection: Selection,
+        mime_types: Vec<String>,
+        options: ReadOptions,
+        sink: Sink,
+    },
+    /// List the mime types offered in a selection.
+    Formats {
+        selection: Selection,
+        on_done: Box<dyn FnOnce(Result<Vec<String>, Error>) + Send>,
+    },
+    /// Tell whether the compositor supports a selection.
+    Supports {
+        selection: Selection,
+        reply: Sender<bool>,
+    },
+    /// Call the given function on every selection change.
+    Watch(Box<dyn FnMut(Selection) -> bool + Send>),
+    /// Shut down the worker.
+    Exit,
+}
+
+/// Spawns a worker that dispatches its own event queue on the given
+/// connection and handles the commands received through `commands`.
+pub fn spawn(
+    connection: Connection,
+    commands: Channel<Command>,
+) -> Option<thread::JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("clipboard_wayland"))
+        .spawn(move || {
+            if let Some(local) = Local::new(connection, commands) {
+                local.run();
+            }
+        })
+        .ok()
+}
+
+/// A worker dispatched by its owner, instead of a thread of its own.
+pub struct Local {
+    event_loop: EventLoop<'static, State>,
+    state: State,
+}
+
+// SAFETY: `EventLoop` and `State` are not `Send` because calloop shares
+// `Rc`s and `RefCell`s between the loop, its `LoopHandle`s and its sources,
+// and because `State::load` shares an `Rc` between two of those sources.
+// Every one of those clones lives inside this `Local`: the only `LoopHandle`
+// is kept by the private `State`, which never leaves it, and every source is
+// registered through that handle. Moving a `Local` moves all of them at once,
+// so they are never reachable from two threads.
+//
+// `Local` stays `!Sync`, and `Clipboard` only reaches it through a `Mutex`,
+// so a single thread dispatches it at a time. Nothing else in it is bound to
+// a thread: the poller of the loop and the Wayland queue are `Send`, and the
+// sinks, providers, sources and watchers handed in are `Send` as well.
+unsafe impl Send for Local {}
+
+impl Local {
+    /// Sets up a worker with its own event queue on the given connection,
+    /// handling the commands received through `commands`.
+    pub fn new(
+        connection: Connection,
+        commands: Channel<Command>,
+    ) -> Option<Self> {
+        let (globals, queue) = registry_queue_init(&connection).ok()?;
+        let event_loop = EventLoop::<State>::try_new().ok()?;
+        let loop_handle = event_loop.handle();
+
+        let state = State::new(&globals, &queue.handle(), loop_handle.clone())?;
+
+        loop_handle
+            .insert_source(commands, |event, _, state| {
+                if let channel::Event::Msg(command) = event {
+                    handle(state, command);
+                }
+            })
+            .ok()?;
+
+        WaylandSource::new(connection, queue)
+            .insert(loop_handle)
+            .ok()?;
+
+        Some(Local { event_loop, state })
+    }
+
+    fn run(mut self) {
+        while !self.state.exit {
+            if self.event_loop.dispatch(None, &mut self.state).is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Handles the pending events and commands, waiting up to the given time
+    /// for the first one.
+    pub fn dispatch(&mut self, timeout: Duration) -> Result<(), Error> {
+        self.event_loop
+            .dispatch(timeout, &mut self.state)
+            .map_err(|error| Error::Io(error.into()))
+    }
+
+    /// Returns when the next timer fires, if any.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.state.deadlines.iter().min().copied()
+    }
+
+    /// Returns a copy of the file descriptor of the event loop, which
+    /// becomes readable once events or commands arrive.
+    pub fn fd(&self) -> io::Result<OwnedFd> {
+        self.event_loop.as_fd().try_clone_to_owned()
+    }
+}
+
+fn handle(state: &mut State, command: Command) {
+    match command {
+        Command::Store {
+            selection,
+            contents,
+            expiry,
+            reply,
+        } => {
+            let contents: Contents = contents.into_iter().collect();
+            let result = state.store(selection, contents);
+
+            if let (Ok(()), Some(expiry)) = (&result, expiry) {
+                state.expire(selection, expiry);
+            }
+
+            let _ = reply.send(result);
+        }
+        Command::Load {
+            selection,
+            mime_types,
+            options,
+            sink,
+        } => {
+            state.load(selection, &mime_types, options, sink);
+        }
+        Command::Formats { selection, on_done } => {
+            on_done(state.formats(selection));
+        }
+        Command::Supports { selection, reply } => {
+            let _ = reply.send(state.supports(selection));
+        }
+        Command::Watch(on_change) => {
+            state.watch(on_change);
+        }
+        Command::Exit => {
+            state.exit = true;
+        }
+    }
+}
@@ -14,14 +14,30 @@ pub enum Error {
     ReplyError(#[from] ReplyError),
     #[error("timeout")]
     Timeout,
+    #[error("cancelled")]
+    Cancelled,
+    #[error("selection is too large")]
+    TooLarge,
+    #[error("empty selection")]
+    EmptySelection,
+    #[error("unsupported target: {0}")]
+    UnsupportedTarget(Atom),
     #[error("unexpected type: {0}")]
     UnexpectedType(Atom),
     #[error("invalid utf8 string: {0}")]
     InvalidUtf8(std::string::FromUtf8Error),
+    #[error("missing extension: {0}")]
+    MissingExtension(&'static str),
     #[error("deadlock")]
     SelectionLocked,
     #[error("invalid selection owner")]
     InvalidOwner,
+    #[error("missing clipboard manager")]
+    MissingClipboardManager,
+    #[error("clipboard worker is dead")]
+    WorkerDied,
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
     #[error("worker communication error")]
     SendError(#[from] mpsc::SendError<Atom>),
 }
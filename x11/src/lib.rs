@@ -1,126 +1,432 @@
 #[forbid(unsafe_code)]
 mod error;
+mod manager;
 
 pub use error::Error;
+pub use manager::ClipboardManager;
 
-use x11rb::connection::Connection as _;
+use x11rb::connection::{Connection as _, RequestConnection as _};
 use x11rb::errors::ConnectError;
+use x11rb::protocol::xfixes::{self, ConnectionExt as _};
 use x11rb::protocol::xproto::{self, Atom, AtomEnum, EventMask, Window};
 use x11rb::protocol::Event;
 use x11rb::rust_connection::RustConnection as Connection;
 use x11rb::wrapper::ConnectionExt;
 
+use rustix::event;
+
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::{mpsc, Arc, Mutex, RwLock, Weak};
 use std::thread;
 use std::time::{Duration, Instant};
 
-const POLL_DURATION: std::time::Duration = Duration::from_micros(50);
+/// The longest a read waits for events at once, so it notices cancellation.
+const WAIT_SLICE: Duration = Duration::from_millis(10);
 
-/// A connection to an X11 [`Clipboard`].
-pub struct Clipboard {
-    reader: Context,
-    writer: Arc<Context>,
-    selections: Arc<RwLock<HashMap<Atom, (Atom, Vec<u8>)>>>,
+/// The bytes taken by a ChangeProperty request besides its data.
+const REQUEST_OVERHEAD: usize = 24;
+
+/// How long the clipboard manager may take to save the clipboard.
+const PERSIST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long the server may take to report its time.
+const TIMESTAMP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How much of a property is read at once, in 32-bit units.
+const CHUNK_LENGTH: u32 = 16 * 1024;
+
+/// The targets that carry UTF-8 text, by preference.
+///
+/// They are the same that Wayland clients use, with the X11 name first.
+const TEXT_TARGETS: [&str; 3] =
+    ["UTF8_STRING", "text/plain;charset=utf-8", "text/plain"];
+
+/// The targets that keep secrets out of clipboard histories, with the value
+/// managers expect.
+const SENSITIVE_HINTS: [(&str, &[u8]); 1] =
+    [("x-kde-passwordManagerHint", b"secret")];
+
+/// The targets used by the selection protocol itself, which do not carry any
+/// data.
+const META_TARGETS: [&str; 6] = [
+    "TARGETS",
+    "MULTIPLE",
+    "TIMESTAMP",
+    "SAVE_TARGETS",
+    "DELETE",
+    "INSERT_SELECTION",
+];
+
+type Selections = Arc<RwLock<HashMap<Atom, Vec<(Atom, Data)>>>>;
+
+type Watchers = Arc<Mutex<Vec<Box<dyn FnMut(Selection) -> bool + Send>>>>;
+
+type Expiries = Arc<Mutex<Vec<Expiry>>>;
+
+/// A selection to give up once its deadline passes, unless it was set again
+/// after the given time.
+struct Expiry {
+    deadline: Instant,
+    selection: Atom,
+    time: xproto::Timestamp,
 }
 
-impl Clipboard {
-    /// Connect to the running X11 server and obtain a [`Clipboard`].
-    pub fn connect() -> Result<Self, Error> {
-        let reader = Context::new(None)?;
-        let writer = Arc::new(Context::new(None)?);
-        let selections = Arc::new(RwLock::new(HashMap::new()));
+/// The conversions in progress, or `None` once the worker stopped.
+type Conversions = Arc<Mutex<Option<Vec<Conversion>>>>;
 
-        let worker = Worker {
-            context: Arc::clone(&writer),
-            selections: Arc::clone(&selections),
-        };
+/// Receives the contents of a conversion as they arrive, returning `false`
+/// once it does not want any more.
+type Sink = Box<dyn FnMut(Result<Chunk, Error>) -> bool + Send>;
 
-        thread::spawn(move || worker.run());
+/// A part of the contents of a conversion.
+enum Chunk {
+    Data(Vec<u8>),
+    /// The contents are complete.
+    End,
+}
 
-        Ok(Clipboard {
-            reader,
-            writer,
-            selections,
-        })
+/// A conversion of a selection asked for by one of our reads, which the
+/// worker receives on its window.
+struct Conversion {
+    property: Atom,
+    selection: Atom,
+    /// The targets still to try, starting with the one asked for.
+    targets: Vec<Atom>,
+    /// The target reported if the owner refuses every one.
+    requested: Atom,
+    /// Whether the owner sends the contents in chunks.
+    incremental: bool,
+    received: usize,
+    deadline: Option<Instant>,
+    options: ReadOptions,
+    sink: Sink,
+}
+
+/// A function producing the contents of a selection in the given format.
+///
+/// It is only called once a requestor asks for the format, and it may be
+/// called again for every request. Returning `None` refuses the request.
+pub type Provider = Arc<dyn Fn(&str) -> Option<Vec<u8>> + Send + Sync>;
+
+/// A function opening a new reader over the contents of a selection.
+///
+/// It is called for every request, so every requestor reads the contents
+/// from the start.
+pub type Source =
+    Arc<dyn Fn() -> io::Result<Box<dyn Read + Send>> + Send + Sync>;
+
+/// The contents offered for a selection target.
+#[derive(Clone)]
+enum Data {
+    Ready(Arc<[u8]>),
+    Lazy { format: String, provider: Provider },
+    Stream(Source),
+}
+
+/// The options of a read.
+#[derive(Debug, Clone)]
+pub struct ReadOptions {
+    /// How long to wait for the selection owner, or forever if `None`.
+    ///
+    /// Defaults to 3 seconds.
+    pub timeout: Option<Duration>,
+    /// Aborts the read with [`Error::Cancelled`] once set to `true`.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Aborts the read with [`Error::TooLarge`] past this many bytes.
+    pub max_bytes: Option<usize>,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            timeout: Some(Duration::from_secs(3)),
+            cancel: None,
+            max_bytes: None,
+        }
     }
+}
 
-    fn read_selection(&self, selection: Atom) -> Result<String, Error> {
-        Ok(String::from_utf8(self.load(
-            selection,
-            self.reader.atoms.utf8_string,
-            self.reader.atoms.property,
-            std::time::Duration::from_secs(3),
-        )?)
-        .map_err(Error::InvalidUtf8)?)
+impl ReadOptions {
+    /// Returns when a read starting now times out, if ever.
+    fn deadline(&self) -> Option<Instant> {
+        // Timeouts too long to represent never expire
+        self.timeout
+            .and_then(|timeout| Instant::now().checked_add(timeout))
     }
 
-    /// Read the current CLIPBOARD [`Clipboard`] value.
-    pub fn read(&self) -> Result<String, Error> {
-        self.read_selection(self.reader.atoms.clipboard)
+    fn is_cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(atomic::Ordering::Relaxed))
     }
+}
+
+/// A selection of the X11 server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Selection {
+    /// The CLIPBOARD selection, used by explicit copy and paste.
+    Clipboard,
+    /// The PRIMARY selection, set by selecting text.
+    Primary,
+    /// The SECONDARY selection, rarely used by modern applications.
+    Secondary,
+}
 
+/// The clipboard shared by every [`Clipboard::connect_shared`] caller, for as
+/// long as one of them is alive.
+static SHARED: Mutex<Weak<Inner>> = Mutex::new(Weak::new());
 
-    /// Read the current PRIMARY [`Clipboard`] value.
-    pub fn read_primary(&self) -> Result<String, Error> {
-        self.read_selection(self.reader.atoms.primary)
+/// The clipboard shared by every [`Clipboard::connect_shared_without_thread`]
+/// caller, for as long as one of them is alive.
+static SHARED_WITHOUT_THREAD: Mutex<Weak<Inner>> = Mutex::new(Weak::new());
+
+/// A connection to an X11 [`Clipboard`].
+///
+/// Clones share the same connections and worker thread.
+#[derive(Clone)]
+pub struct Clipboard {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    reader: Context,
+    /// Only one request can be awaited on the reader at a time.
+    reading: Mutex<()>,
+    writer: Arc<Context>,
+    selections: Selections,
+    watchers: Option<Watchers>,
+    expiries: Expiries,
+    conversions: Conversions,
+    /// The properties interned for the conversions, each used by a single
+    /// conversion at a time.
+    properties: Mutex<Vec<Atom>>,
+    /// Whether the clipboard is handed to the clipboard manager on drop.
+    persist_on_drop: AtomicBool,
+    /// The worker, unless it runs in a thread of its own.
+    worker: Mutex<Option<Worker>>,
+    thread: Mutex<Option<thread::JoinHandle<()>>>,
+    shut_down: AtomicBool,
+}
+
+impl Inner {
+    fn is_threaded(&self) -> bool {
+        self.thread.lock().is_ok_and(|thread| thread.is_some())
     }
 
-    fn write_selection(&mut self, selection: Atom, contents: String) -> Result<(), Error> {
-        let target = self.writer.atoms.utf8_string;
+    /// Makes sure the events read along with a reply on the writer get
+    /// served, since they are queued without making its connection readable.
+    fn serve_queued(&self) -> Result<(), Error> {
+        if self.is_threaded() {
+            self.wake()
+        } else {
+            self.dispatch_pending()
+        }
+    }
 
-        self.selections
-            .write()
+    /// Serves the events received by the writer, if nobody else does.
+    fn dispatch_pending(&self) -> Result<(), Error> {
+        match self
+            .worker
+            .lock()
             .map_err(|_| Error::SelectionLocked)?
-            .insert(selection, (target, contents.into()));
+            .as_mut()
+        {
+            Some(worker) => worker.dispatch_pending(),
+            None => Ok(()),
+        }
+    }
 
-        let _ = xproto::set_selection_owner(
+    /// Returns the current time of the server, which requests racing with
+    /// other clients need instead of `CurrentTime`.
+    fn timestamp(&self) -> Result<xproto::Timestamp, Error> {
+        let atoms = &self.reader.atoms;
+
+        let _reading =
+            self.reading.lock().map_err(|_| Error::SelectionLocked)?;
+
+        // Appending nothing to a property still reports when it happened
+        let _ = self.reader.connection.change_property8(
+            xproto::PropMode::APPEND,
+            self.reader.window,
+            atoms.timestamp,
+            AtomEnum::STRING,
+            &[],
+        )?;
+        self.reader.connection.flush()?;
+
+        let deadline = Instant::now() + TIMESTAMP_TIMEOUT;
+
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+
+            if left.is_zero() {
+                return Err(Error::Timeout);
+            }
+
+            if let Some(Event::PropertyNotify(event)) =
+                self.reader.next_event(left.min(WAIT_SLICE))?
+            {
+                if event.window == self.reader.window
+                    && event.atom == atoms.timestamp
+                {
+                    return Ok(event.time);
+                }
+            }
+        }
+    }
+
+    /// Makes the worker look at its deadlines again, if it is waiting for
+    /// events.
+    fn wake(&self) -> Result<(), Error> {
+        let _ = xproto::send_event(
             &self.writer.connection,
+            false,
             self.writer.window,
-            selection,
-            x11rb::CURRENT_TIME,
+            EventMask::NO_EVENT,
+            xproto::ClientMessageEvent::new(
+                32,
+                self.writer.window,
+                x11rb::NONE,
+                [0; 5],
+            ),
         )?;
+        self.writer.connection.flush()?;
+
+        Ok(())
+    }
+
+    /// Asks the owner of the selection to convert it to the first of the
+    /// given targets it supports, for the worker to hand the contents to
+    /// `sink`.
+    ///
+    /// Every failure, including the ones found here, goes to `sink`.
+    fn convert(
+        &self,
+        selection: Atom,
+        targets: Vec<Atom>,
+        options: &ReadOptions,
+        mut sink: Sink,
+    ) {
+        // Round trips are made before taking the lock, so that reads do not
+        // wait for each other
+        if let Err(error) = self.check_owner(selection) {
+            let _ = sink(Err(error));
+            return;
+        }
+
+        loop {
+            let Ok(mut guard) = self.conversions.lock() else {
+                let _ = sink(Err(Error::SelectionLocked));
+                return;
+            };
+
+            let Some(conversions) = guard.as_mut() else {
+                drop(guard);
+                let _ = sink(Err(Error::WorkerDied));
+                return;
+            };
+
+            // Every conversion in progress needs a property of its own
+            let property = self.properties.lock().ok().and_then(|properties| {
+                properties.iter().copied().find(|&property| {
+                    conversions
+                        .iter()
+                        .all(|conversion| conversion.property != property)
+                })
+            });
 
-        let _ = self.writer.connection.flush()?;
+            let Some(property) = property else {
+                drop(guard);
 
-        let reply =
-            xproto::get_selection_owner(&self.writer.connection, selection)
+                if let Err(error) = self.add_property() {
+                    let _ = sink(Err(error));
+                    return;
+                }
+
+                continue;
+            };
+
+            // The worker only looks at the answer once the conversion is in
+            // the list, since it needs the lock
+            if let Err(error) = self.request(selection, targets[0], property) {
+                drop(guard);
+                let _ = sink(Err(error));
+                return;
+            }
+
+            conversions.push(Conversion {
+                property,
+                selection,
+                requested: targets[0],
+                targets,
+                incremental: false,
+                received: 0,
+                deadline: options.deadline(),
+                options: options.clone(),
+                sink,
+            });
+
+            drop(guard);
+
+            // The worker fails the conversion if the connection is gone
+            let _ = self.wake();
+
+            return;
+        }
+    }
+
+    /// Fails with [`Error::EmptySelection`] if the selection has no owner.
+    fn check_owner(&self, selection: Atom) -> Result<(), Error> {
+        let owner =
+            xproto::get_selection_owner(&self.reader.connection, selection)
                 .map_err(Into::into)
-                .and_then(|cookie| cookie.reply())?;
+                .and_then(|cookie| cookie.reply())?
+                .owner;
 
-        if reply.owner == self.writer.window {
-            Ok(())
+        if owner == x11rb::NONE {
+            Err(Error::EmptySelection)
         } else {
-            Err(Error::InvalidOwner)
+            Ok(())
         }
     }
 
-    /// Write a new value to the CLIPBOARD [`Clipboard`].
-    pub fn write(&mut self, contents: String) -> Result<(), Error> {
-        let selection = self.writer.atoms.clipboard;
-        self.write_selection(selection, contents)
-    }
+    /// Interns one more property for the conversions to use.
+    fn add_property(&self) -> Result<(), Error> {
+        let count = self
+            .properties
+            .lock()
+            .map_err(|_| Error::SelectionLocked)?
+            .len();
 
-    /// Write a new value to the PRIMARY [`Clipboard`].
-    pub fn write_primary(&mut self, contents: String) -> Result<(), Error> {
-        let selection = self.writer.atoms.primary;
-        self.write_selection(selection, contents)
+        let property = self
+            .reader
+            .intern(&format!("WINDOW_CLIPBOARD_READ_{count}"))?;
+
+        let mut properties =
+            self.properties.lock().map_err(|_| Error::SelectionLocked)?;
+
+        // Another read may have interned it in the meantime
+        if !properties.contains(&property) {
+            properties.push(property);
+        }
+
+        Ok(())
     }
 
-    /// load value.
-    fn load(
+    /// Sends the conversion of the selection to the given target, stored in
+    /// the given property of the writer window.
+    fn request(
         &self,
         selection: Atom,
         target: Atom,
         property: Atom,
-        timeout: impl Into<Option<Duration>>,
-    ) -> Result<Vec<u8>, Error> {
-        let mut buff = Vec::new();
-        let timeout = timeout.into();
-
+    ) -> Result<(), Error> {
         let _ = xproto::convert_selection(
-            &self.reader.connection,
-            self.reader.window,
+            &self.writer.connection,
+            self.writer.window,
             selection,
             target,
             property,
@@ -128,193 +434,1390 @@ impl Clipboard {
                                  // Clients should not use CurrentTime for the time argument of a ConvertSelection request.
                                  // Instead, they should use the timestamp of the event that caused the request to be made.
         )?;
-        let _ = self.reader.connection.flush()?;
-
-        self.process_event(&mut buff, selection, target, property, timeout)?;
 
-        let _ = xproto::delete_property(
-            &self.reader.connection,
-            self.reader.window,
-            property,
-        )?;
-        let _ = self.reader.connection.flush()?;
+        self.writer.connection.flush()?;
 
-        Ok(buff)
+        Ok(())
     }
 
-    fn process_event<T>(
+    /// Waits for the next message of a conversion, serving our own
+    /// selections in the meantime.
+    fn next_chunk(
         &self,
-        buff: &mut Vec<u8>,
-        selection: Atom,
-        target: Atom,
-        property: Atom,
-        timeout: T,
-    ) -> Result<(), Error>
-    where
-        T: Into<Option<Duration>>,
-    {
-        let mut is_incr = false;
-        let timeout = timeout.into();
-        let start_time = if timeout.is_some() {
-            Some(Instant::now())
-        } else {
-            None
-        };
+        chunks: &mpsc::Receiver<Result<Chunk, Error>>,
+    ) -> Result<Chunk, Error> {
+        // The worker thread ends the conversion once it times out or is
+        // cancelled
+        if self.is_threaded() {
+            return chunks.recv().unwrap_or(Err(Error::WorkerDied));
+        }
 
         loop {
-            if timeout
-                .into_iter()
-                .zip(start_time)
-                .next()
-                .map(|(timeout, time)| (Instant::now() - time) >= timeout)
-                .unwrap_or(false)
-            {
-                return Err(Error::Timeout);
-            }
+            // Without a worker thread, the answer is only received here
+            self.dispatch_pending()?;
 
-            let event = match self.reader.connection.poll_for_event()? {
-                Some(event) => event,
-                None => {
-                    thread::park_timeout(POLL_DURATION);
-                    continue;
+            match chunks.recv_timeout(WAIT_SLICE) {
+                Ok(chunk) => return chunk,
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(Error::WorkerDied);
                 }
-            };
+            }
+        }
+    }
 
-            match event {
-                Event::SelectionNotify(event) => {
-                    if event.selection != selection {
-                        continue;
-                    };
+    /// Asks the clipboard manager to take a copy of the CLIPBOARD contents,
+    /// waiting until it is done.
+    fn persist(&self) -> Result<(), Error> {
+        let atoms = &self.reader.atoms;
 
-                    // Note that setting the property argument to None indicates that the
-                    // conversion requested could not be made.
-                    if event.property == AtomEnum::NONE.into() {
-                        break;
-                    }
+        // Only our own contents need to be saved
+        let targets: Vec<Atom> = match self
+            .selections
+            .read()
+            .map_err(|_| Error::SelectionLocked)?
+            .get(&atoms.clipboard)
+        {
+            Some(targets) => {
+                targets.iter().map(|&(target, _)| target).collect()
+            }
+            None => return Ok(()),
+        };
 
-                    let reply = xproto::get_property(
-                        &self.reader.connection,
-                        false,
-                        self.reader.window,
-                        event.property,
-                        Atom::from(AtomEnum::ANY),
-                        buff.len() as u32,
-                        ::std::u32::MAX, // FIXME reasonable buffer size
-                    )
-                    .map_err(Into::into)
-                    .and_then(|cookie| cookie.reply())?;
+        let _reading =
+            self.reading.lock().map_err(|_| Error::SelectionLocked)?;
 
-                    if reply.type_ == self.reader.atoms.incr {
-                        if let Some(&size) = reply.value.get(0) {
-                            buff.reserve(size as usize);
-                        }
+        let manager = xproto::get_selection_owner(
+            &self.reader.connection,
+            atoms.clipboard_manager,
+        )
+        .map_err(Into::into)
+        .and_then(|cookie| cookie.reply())?
+        .owner;
+
+        if manager == x11rb::NONE {
+            return Err(Error::MissingClipboardManager);
+        }
+
+        // The manager saves the targets listed in the property
+        let _ = self.reader.connection.change_property32(
+            xproto::PropMode::REPLACE,
+            self.reader.window,
+            atoms.property,
+            AtomEnum::ATOM,
+            &targets,
+        )?;
+
+        let _ = xproto::convert_selection(
+            &self.reader.connection,
+            self.reader.window,
+            atoms.clipboard_manager,
+            atoms.save_targets,
+            atoms.property,
+            x11rb::CURRENT_TIME,
+        )?;
+        self.reader.connection.flush()?;
+
+        let deadline = Instant::now() + PERSIST_TIMEOUT;
 
-                        let _ = xproto::delete_property(
-                            &self.reader.connection,
-                            self.reader.window,
-                            property,
-                        );
+        // The worker serves the conversions of the manager in the meantime
+        let result = loop {
+            self.dispatch_pending()?;
 
-                        let _ = self.reader.connection.flush();
-                        is_incr = true;
+            let left = deadline.saturating_duration_since(Instant::now());
 
-                        continue;
-                    } else if reply.type_ != target {
-                        return Err(Error::UnexpectedType(reply.type_));
+            if left.is_zero() {
+                break Err(Error::Timeout);
+            }
+
+            match self.reader.next_event(left.min(WAIT_SLICE))? {
+                Some(Event::SelectionNotify(event))
+                    if event.selection == atoms.clipboard_manager =>
+                {
+                    if event.property == x11rb::NONE {
+                        break Err(Error::UnsupportedTarget(
+                            atoms.save_targets,
+                        ));
                     }
 
-                    buff.extend_from_slice(&reply.value);
-                    break;
+                    break Ok(());
                 }
-                Event::PropertyNotify(event) if is_incr => {
-                    if event.state != xproto::Property::NEW_VALUE {
-                        continue;
-                    };
+                _ => {}
+            }
+        };
+
+        let _ = xproto::delete_property(
+            &self.reader.connection,
+            self.reader.window,
+            atoms.property,
+        )?;
+        self.reader.connection.flush()?;
+
+        result
+    }
+
+    /// Releases our selections, destroys our windows and stops the worker,
+    /// handing the clipboard to the clipboard manager first if asked to.
+    ///
+    /// It does nothing once done.
+    fn shutdown(&self) -> Result<(), Error> {
+        if self.shut_down.swap(true, atomic::Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        // The hand-off is best effort, since it needs a clipboard manager
+        if self.persist_on_drop.load(atomic::Ordering::Relaxed) {
+            let _ = self.persist();
+        }
+
+        let writer = &self.writer;
+
+        let owned: Vec<Atom> = self
+            .selections
+            .write()
+            .map_err(|_| Error::SelectionLocked)?
+            .drain()
+            .map(|(selection, _)| selection)
+            .collect();
 
-                    let length = xproto::get_property(
-                        &self.reader.connection,
-                        false,
-                        self.reader.window,
-                        property,
-                        Atom::from(AtomEnum::ANY),
-                        0,
-                        0,
-                    )
+        for selection in owned {
+            let owner =
+                xproto::get_selection_owner(&writer.connection, selection)
                     .map_err(Into::into)
                     .and_then(|cookie| cookie.reply())?
-                    .bytes_after;
-
-                    let reply = xproto::get_property(
-                        &self.reader.connection,
-                        true,
-                        self.reader.window,
-                        property,
-                        Atom::from(AtomEnum::ANY),
-                        0,
-                        length,
-                    )
-                    .map_err(Into::into)
-                    .and_then(|cookie| cookie.reply())?;
+                    .owner;
 
-                    if reply.type_ != target {
-                        continue;
-                    };
+            // Somebody else may have taken it already
+            if owner == writer.window {
+                let _ = xproto::set_selection_owner(
+                    &writer.connection,
+                    x11rb::NONE,
+                    selection,
+                    x11rb::CURRENT_TIME,
+                )?;
+            }
+        }
 
-                    if reply.value_len != 0 {
-                        buff.extend_from_slice(&reply.value);
-                    } else {
-                        break;
-                    }
-                }
-                _ => {}
+        // The worker stops once its window is gone
+        let _ = xproto::destroy_window(&writer.connection, writer.window)?;
+        writer.connection.flush()?;
+
+        let thread = self
+            .thread
+            .lock()
+            .map_err(|_| Error::SelectionLocked)?
+            .take();
+
+        if let Some(thread) = thread {
+            // A provider may drop the last clipboard from the worker itself
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
             }
         }
 
+        let _ = self
+            .worker
+            .lock()
+            .map_err(|_| Error::SelectionLocked)?
+            .take();
+
+        let _ = xproto::destroy_window(
+            &self.reader.connection,
+            self.reader.window,
+        )?;
+        self.reader.connection.flush()?;
+
         Ok(())
     }
 }
 
-pub struct Context {
-    pub connection: Connection,
-    pub screen: usize,
-    pub window: Window,
-    pub atoms: Atoms,
+/// The connection receiving the requests of other clients, which
+/// [`Clipboard::dispatch_pending`] serves once readable.
+///
+/// Requests read along with the replies of other calls do not make it
+/// readable, so those calls serve them before returning.
+impl AsFd for Clipboard {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.writer.connection.stream().as_fd()
+    }
 }
 
-#[derive(Clone, Debug)]
-pub struct Atoms {
-    pub primary: Atom,
-    pub clipboard: Atom,
-    pub property: Atom,
-    pub targets: Atom,
-    pub string: Atom,
-    pub utf8_string: Atom,
-    pub incr: Atom,
+impl AsRawFd for Clipboard {
+    fn as_raw_fd(&self) -> RawFd {
+        self.as_fd().as_raw_fd()
+    }
 }
 
-#[inline]
-fn get_atom(connection: &Connection, name: &str) -> Result<Atom, Error> {
-    x11rb::protocol::xproto::intern_atom(connection, false, name.as_bytes())
-        .map_err(Into::into)
-        .and_then(|cookie| cookie.reply())
-        .map(|reply| reply.atom)
-        .map_err(Into::into)
+impl Drop for Inner {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
 }
 
-impl Context {
-    pub fn new(displayname: Option<&str>) -> Result<Self, Error> {
-        let (connection, screen) = Connection::connect(displayname)?;
-        let window = connection.generate_id().map_err(|_| {
-            Error::ConnectionFailed(ConnectError::InvalidScreen)
-        })?;
+impl Clipboard {
+    /// Connect to the running X11 server and obtain a [`Clipboard`].
+    pub fn connect() -> Result<Self, Error> {
+        Self::connect_with(None, true)
+    }
 
-        {
-            let screen =
-                connection.setup().roots.get(screen as usize).ok_or(
-                    Error::ConnectionFailed(ConnectError::InvalidScreen),
-                )?;
+    /// Connect to the running X11 server without spawning any threads.
+    ///
+    /// Other clients are only served while [`Clipboard::dispatch_pending`]
+    /// is called; usually, whenever the file descriptor of the [`Clipboard`]
+    /// becomes readable or [`Clipboard::next_deadline`] passes. Blocking
+    /// reads serve the clipboard until they are done, while the callbacks of
+    /// the `*_async` reads are called from [`Clipboard::dispatch_pending`].
+    pub fn connect_without_thread() -> Result<Self, Error> {
+        Self::connect_with(None, false)
+    }
 
-            let _ = xproto::create_window(
+    /// Connects a [`Clipboard`] whose worker hands `SAVE_TARGETS` requests
+    /// to the given [`ClipboardManager`] channel, and runs in a thread of its
+    /// own if `threaded`.
+    fn connect_with(
+        saves: Option<mpsc::Sender<manager::Message>>,
+        threaded: bool,
+    ) -> Result<Self, Error> {
+        let reader = Context::new(None)?;
+        let writer = Arc::new(Context::new(None)?);
+        let selections = Arc::new(RwLock::new(HashMap::new()));
+
+        // Changes are only reported by servers with the XFixes extension
+        let watchers = if writer.watch_selections()? {
+            Some(Arc::new(Mutex::new(Vec::new())))
+        } else {
+            None
+        };
+
+        let expiries = Arc::new(Mutex::new(Vec::new()));
+        let conversions = Arc::new(Mutex::new(Some(Vec::new())));
+
+        let worker = Worker {
+            context: Arc::clone(&writer),
+            selections: Arc::clone(&selections),
+            watchers: watchers.clone(),
+            expiries: Arc::clone(&expiries),
+            conversions: Arc::clone(&conversions),
+            transfers: HashMap::new(),
+            saves,
+        };
+
+        let (worker, thread) = if threaded {
+            (None, Some(thread::spawn(move || worker.run())))
+        } else {
+            (Some(worker), None)
+        };
+
+        Ok(Clipboard {
+            inner: Arc::new(Inner {
+                reader,
+                reading: Mutex::new(()),
+                writer,
+                selections,
+                watchers,
+                expiries,
+                conversions,
+                properties: Mutex::new(Vec::new()),
+                persist_on_drop: AtomicBool::new(false),
+                worker: Mutex::new(worker),
+                thread: Mutex::new(thread),
+                shut_down: AtomicBool::new(false),
+            }),
+        })
+    }
+
+    /// Obtain a [`Clipboard`] sharing its connections and worker thread with
+    /// every other shared [`Clipboard`] of the process, connecting only if
+    /// none is alive.
+    ///
+    /// The whole process then uses two connections and a single thread, but
+    /// shares its settings too: [`Clipboard::set_persist_on_drop`] applies
+    /// to every shared [`Clipboard`].
+    pub fn connect_shared() -> Result<Self, Error> {
+        Self::shared(&SHARED, Self::connect)
+    }
+
+    /// Obtain a [`Clipboard`] sharing its connections with every other
+    /// [`Clipboard`] of the process obtained this way, like
+    /// [`Clipboard::connect_shared`], without spawning any threads.
+    ///
+    /// Calling [`Clipboard::dispatch_pending`] on any of them serves all of
+    /// them, like for [`Clipboard::connect_without_thread`].
+    pub fn connect_shared_without_thread() -> Result<Self, Error> {
+        Self::shared(&SHARED_WITHOUT_THREAD, Self::connect_without_thread)
+    }
+
+    /// Returns the clipboard kept in `shared`, connecting with `connect` if
+    /// none is alive.
+    fn shared(
+        shared: &Mutex<Weak<Inner>>,
+        connect: fn() -> Result<Self, Error>,
+    ) -> Result<Self, Error> {
+        let mut shared = shared.lock().map_err(|_| Error::SelectionLocked)?;
+
+        // A shared clipboard that was shut down cannot be reused
+        if let Some(inner) = shared
+            .upgrade()
+            .filter(|inner| !inner.shut_down.load(atomic::Ordering::SeqCst))
+        {
+            return Ok(Clipboard { inner });
+        }
+
+        let clipboard = connect()?;
+        *shared = Arc::downgrade(&clipboard.inner);
+
+        Ok(clipboard)
+    }
+
+    /// Returns the targets carrying the given format, by preference.
+    fn targets(&self, format: &str) -> Result<Vec<Atom>, Error> {
+        aliases(format)
+            .into_iter()
+            .map(|name| self.inner.reader.intern(name))
+            .collect()
+    }
+
+    /// Read the current CLIPBOARD [`Clipboard`] value.
+    pub fn read(&self) -> Result<String, Error> {
+        self.read_from(Selection::Clipboard)
+    }
+
+    /// Read the current PRIMARY [`Clipboard`] value.
+    pub fn read_primary(&self) -> Result<String, Error> {
+        self.read_from(Selection::Primary)
+    }
+
+    /// Read the current value of the given [`Selection`].
+    pub fn read_from(&self, selection: Selection) -> Result<String, Error> {
+        self.read_with(selection, &ReadOptions::default())
+    }
+
+    /// Read the current value of the given [`Selection`] with the given
+    /// [`ReadOptions`].
+    pub fn read_with(
+        &self,
+        selection: Selection,
+        options: &ReadOptions,
+    ) -> Result<String, Error> {
+        String::from_utf8(self.read_format_with(
+            selection,
+            TEXT_TARGETS[0],
+            options,
+        )?)
+        .map_err(Error::InvalidUtf8)
+    }
+
+    /// Read the current CLIPBOARD [`Clipboard`] value in the given format.
+    ///
+    /// The format is the name of the target to convert the selection to;
+    /// usually, a MIME type.
+    pub fn read_format(&self, format: &str) -> Result<Vec<u8>, Error> {
+        self.read_format_from(Selection::Clipboard, format)
+    }
+
+    /// Read the current value of the given [`Selection`] in the given format.
+    pub fn read_format_from(
+        &self,
+        selection: Selection,
+        format: &str,
+    ) -> Result<Vec<u8>, Error> {
+        self.read_format_with(selection, format, &ReadOptions::default())
+    }
+
+    /// Read the current value of the given [`Selection`] in the given format
+    /// with the given [`ReadOptions`].
+    pub fn read_format_with(
+        &self,
+        selection: Selection,
+        format: &str,
+        options: &ReadOptions,
+    ) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::new();
+
+        let _ = self.read_to(selection, format, &mut data, options)?;
+
+        Ok(data)
+    }
+
+    /// Streams the current value of the given [`Selection`] in the given
+    /// format into `output` as it arrives, returning the bytes written.
+    pub fn read_to(
+        &self,
+        selection: Selection,
+        format: &str,
+        output: &mut dyn Write,
+        options: &ReadOptions,
+    ) -> Result<usize, Error> {
+        self.load_to(
+            self.inner.reader.atoms.selection(selection),
+            self.targets(format)?,
+            output,
+            options,
+        )
+    }
+
+    /// Reads the text contents of the given [`Selection`] without blocking,
+    /// calling `on_done` once they arrive.
+    ///
+    /// `on_done` is called from the thread serving the clipboard, or from
+    /// [`Clipboard::dispatch_pending`] without one, so it must not wait for
+    /// the clipboard.
+    pub fn read_async(
+        &self,
+        selection: Selection,
+        options: &ReadOptions,
+        on_done: impl FnOnce(Result<String, Error>) + Send + 'static,
+    ) {
+        self.read_format_async(selection, TEXT_TARGETS[0], options, |data| {
+            on_done(data.and_then(|data| {
+                String::from_utf8(data).map_err(Error::InvalidUtf8)
+            }));
+        });
+    }
+
+    /// Reads the contents of the given [`Selection`] in the given format
+    /// without blocking, calling `on_done` like [`Clipboard::read_async`].
+    pub fn read_format_async(
+        &self,
+        selection: Selection,
+        format: &str,
+        options: &ReadOptions,
+        on_done: impl FnOnce(Result<Vec<u8>, Error>) + Send + 'static,
+    ) {
+        let mut sink = collect(on_done);
+
+        match self.targets(format) {
+            Ok(targets) => self.inner.convert(
+                self.inner.reader.atoms.selection(selection),
+                targets,
+                options,
+                sink,
+            ),
+            Err(error) => {
+                let _ = sink(Err(error));
+            }
+        }
+    }
+
+    /// Lists the formats offered by the current owner of the given
+    /// [`Selection`] without blocking, calling `on_done` like
+    /// [`Clipboard::read_async`].
+    pub fn available_formats_async(
+        &self,
+        selection: Selection,
+        on_done: impl FnOnce(Result<Vec<String>, Error>) + Send + 'static,
+    ) {
+        let writer = Arc::clone(&self.inner.writer);
+
+        let sink = collect(move |data| {
+            let formats = match data {
+                Ok(data) => writer.atom_names(&parse_atoms(&data)).map(formats),
+                Err(Error::EmptySelection) => Ok(Vec::new()),
+                Err(error) => Err(error),
+            };
+
+            on_done(formats);
+        });
+
+        self.inner.convert(
+            self.inner.reader.atoms.selection(selection),
+            vec![self.inner.reader.atoms.targets],
+            &ReadOptions::default(),
+            sink,
+        );
+    }
+
+    /// Returns the targets offered by the owner of the selection.
+    fn selection_targets(&self, selection: Atom) -> Result<Vec<Atom>, Error> {
+        match self.load(
+            selection,
+            self.inner.reader.atoms.targets,
+            &ReadOptions::default(),
+        ) {
+            Ok(data) => Ok(parse_atoms(&data)),
+            Err(Error::EmptySelection) => Ok(Vec::new()),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn selection_formats(&self, selection: Atom) -> Result<Vec<String>, Error> {
+        let targets = self.selection_targets(selection)?;
+
+        Ok(formats(self.inner.reader.atom_names(&targets)?))
+    }
+
+    /// Returns the formats offered by the current owner of the CLIPBOARD
+    /// [`Clipboard`].
+    ///
+    /// These are the names of the targets the owner can convert the
+    /// selection to; usually, MIME types.
+    pub fn available_formats(&self) -> Result<Vec<String>, Error> {
+        self.available_formats_in(Selection::Clipboard)
+    }
+
+    /// Returns the formats offered by the current owner of the given
+    /// [`Selection`].
+    pub fn available_formats_in(
+        &self,
+        selection: Selection,
+    ) -> Result<Vec<String>, Error> {
+        self.selection_formats(self.inner.reader.atoms.selection(selection))
+    }
+
+    /// Takes the ownership of the selection at the given time, offering the
+    /// given contents.
+    fn write_selection(
+        &self,
+        selection: Atom,
+        formats: Vec<(&str, Data)>,
+        time: xproto::Timestamp,
+    ) -> Result<(), Error> {
+        let mut targets: Vec<(Atom, Data)> = Vec::new();
+
+        for (format, data) in formats {
+            for name in aliases(format) {
+                let target = self.inner.reader.intern(name)?;
+
+                if !targets.iter().any(|(known, _)| *known == target) {
+                    targets.push((target, data.clone()));
+                }
+            }
+        }
+
+        self.inner
+            .selections
+            .write()
+            .map_err(|_| Error::SelectionLocked)?
+            .insert(selection, targets);
+
+        let _ = xproto::set_selection_owner(
+            &self.inner.writer.connection,
+            self.inner.writer.window,
+            selection,
+            time,
+        )?;
+
+        self.inner.writer.connection.flush()?;
+
+        let reply = xproto::get_selection_owner(
+            &self.inner.writer.connection,
+            selection,
+        )
+        .map_err(Into::into)
+        .and_then(|cookie| cookie.reply())?;
+
+        self.inner.serve_queued()?;
+
+        if reply.owner == self.inner.writer.window {
+            Ok(())
+        } else {
+            Err(Error::InvalidOwner)
+        }
+    }
+
+    /// Write a new value to the CLIPBOARD [`Clipboard`].
+    pub fn write(&self, contents: String) -> Result<(), Error> {
+        self.write_to(Selection::Clipboard, contents)
+    }
+
+    /// Write a new value to the PRIMARY [`Clipboard`].
+    pub fn write_primary(&self, contents: String) -> Result<(), Error> {
+        self.write_to(Selection::Primary, contents)
+    }
+
+    /// Write a new value to the given [`Selection`].
+    pub fn write_to(
+        &self,
+        selection: Selection,
+        contents: String,
+    ) -> Result<(), Error> {
+        self.write_formats_to(
+            selection,
+            &[(TEXT_TARGETS[0], contents.as_bytes())],
+        )
+    }
+
+    /// Write a new value to the CLIPBOARD [`Clipboard`] until the given time
+    /// passes.
+    pub fn write_with_expiry(
+        &self,
+        contents: String,
+        expiry: Duration,
+    ) -> Result<(), Error> {
+        self.write_with_expiry_to(Selection::Clipboard, contents, expiry)
+    }
+
+    /// Write a new value to the given [`Selection`] until the given time
+    /// passes.
+    ///
+    /// The ownership is only given up if nobody, including us, set the
+    /// selection again by then. Without a worker thread, this happens in the
+    /// [`Clipboard::dispatch_pending`] call following
+    /// [`Clipboard::next_deadline`].
+    pub fn write_with_expiry_to(
+        &self,
+        selection: Selection,
+        contents: String,
+        expiry: Duration,
+    ) -> Result<(), Error> {
+        let selection = self.inner.writer.atoms.selection(selection);
+
+        // The server only lets us give up the ownership taken at this time
+        let time = self.inner.timestamp()?;
+
+        self.write_selection(
+            selection,
+            vec![(
+                TEXT_TARGETS[0],
+                Data::Ready(Arc::from(contents.into_bytes())),
+            )],
+            time,
+        )?;
+
+        let Some(deadline) = Instant::now().checked_add(expiry) else {
+            return Ok(());
+        };
+
+        self.inner
+            .expiries
+            .lock()
+            .map_err(|_| Error::SelectionLocked)?
+            .push(Expiry {
+                deadline,
+                selection,
+                time,
+            });
+
+        self.inner.wake()
+    }
+
+    /// Returns when [`Clipboard::dispatch_pending`] must be called next, for
+    /// the expiries of [`Clipboard::write_with_expiry_to`] and the pending
+    /// reads, if ever.
+    ///
+    /// It only matters without a worker thread, since nothing else gives up
+    /// the selections or fails the reads that time out or are cancelled.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        next_deadline(&self.inner.expiries, &self.inner.conversions)
+    }
+
+    /// Returns whether the clipboard is served by a thread of its own, in
+    /// which case [`Clipboard::dispatch_pending`] does nothing.
+    pub fn is_threaded(&self) -> bool {
+        self.inner.is_threaded()
+    }
+
+    /// Write a secret value to the CLIPBOARD [`Clipboard`], asking clipboard
+    /// managers not to record it.
+    pub fn write_sensitive(&self, contents: String) -> Result<(), Error> {
+        self.write_sensitive_to(Selection::Clipboard, contents)
+    }
+
+    /// Write a secret value to the given [`Selection`], asking clipboard
+    /// managers not to record it.
+    pub fn write_sensitive_to(
+        &self,
+        selection: Selection,
+        contents: String,
+    ) -> Result<(), Error> {
+        let formats: Vec<(&str, &[u8])> =
+            std::iter::once((TEXT_TARGETS[0], contents.as_bytes()))
+                .chain(SENSITIVE_HINTS)
+                .collect();
+
+        self.write_formats_to(selection, &formats)
+    }
+
+    /// Write new values to the CLIPBOARD [`Clipboard`], one per format.
+    ///
+    /// Each format is offered as a target with the same name; usually, a
+    /// MIME type.
+    pub fn write_formats(
+        &self,
+        formats: &[(&str, &[u8])],
+    ) -> Result<(), Error> {
+        self.write_formats_to(Selection::Clipboard, formats)
+    }
+
+    /// Write new values to the given [`Selection`], one per format.
+    pub fn write_formats_to(
+        &self,
+        selection: Selection,
+        formats: &[(&str, &[u8])],
+    ) -> Result<(), Error> {
+        let selection = self.inner.writer.atoms.selection(selection);
+        self.write_selection(
+            selection,
+            formats
+                .iter()
+                .map(|(format, value)| {
+                    (*format, Data::Ready(Arc::from(*value)))
+                })
+                .collect(),
+            x11rb::CURRENT_TIME,
+        )
+    }
+
+    /// Offers the CLIPBOARD [`Clipboard`] in the given formats, without
+    /// producing any contents until a requestor asks for one of them.
+    pub fn write_lazy(
+        &self,
+        formats: &[&str],
+        provider: Provider,
+    ) -> Result<(), Error> {
+        self.write_lazy_to(Selection::Clipboard, formats, provider)
+    }
+
+    /// Offers the given [`Selection`] in the given formats, calling the
+    /// [`Provider`] from the thread serving the clipboard whenever a
+    /// requestor asks for one of them.
+    pub fn write_lazy_to(
+        &self,
+        selection: Selection,
+        formats: &[&str],
+        provider: Provider,
+    ) -> Result<(), Error> {
+        let selection = self.inner.writer.atoms.selection(selection);
+        self.write_selection(
+            selection,
+            formats
+                .iter()
+                .map(|format| {
+                    let data = Data::Lazy {
+                        format: String::from(*format),
+                        provider: Arc::clone(&provider),
+                    };
+
+                    (*format, data)
+                })
+                .collect(),
+            x11rb::CURRENT_TIME,
+        )
+    }
+
+    /// Hands the contents of the CLIPBOARD [`Clipboard`] over to the
+    /// clipboard manager, so they stay available once the process exits.
+    ///
+    /// Fails with [`Error::MissingClipboardManager`] if no clipboard manager
+    /// is running.
+    pub fn persist(&self) -> Result<(), Error> {
+        self.inner.persist()
+    }
+
+    /// Sets whether the contents of the CLIPBOARD [`Clipboard`] are handed
+    /// over to the clipboard manager when the last clone is dropped.
+    pub fn set_persist_on_drop(&self, persist: bool) {
+        self.inner
+            .persist_on_drop
+            .store(persist, atomic::Ordering::Relaxed);
+    }
+
+    /// Shuts the [`Clipboard`] down, releasing the selections we own,
+    /// destroying our windows and stopping the worker thread.
+    ///
+    /// The contents are handed over to the clipboard manager first if
+    /// [`Clipboard::set_persist_on_drop`] was enabled. This also happens when
+    /// the last clone is dropped; shutting down explicitly reports errors.
+    ///
+    /// Every clone stops working afterwards.
+    pub fn shutdown(&self) -> Result<(), Error> {
+        self.inner.shutdown()
+    }
+
+    /// Serves the pending requests of other clients, and reports the pending
+    /// selection changes to watchers, without blocking.
+    ///
+    /// It does nothing unless connected with
+    /// [`Clipboard::connect_without_thread`].
+    pub fn dispatch_pending(&self) -> Result<(), Error> {
+        self.inner.dispatch_pending()
+    }
+
+    /// Returns whether changes can be watched, which requires the XFixes
+    /// extension.
+    pub fn can_watch(&self) -> bool {
+        self.inner.watchers.is_some()
+    }
+
+    /// Offers the clipboard in the given format, reading the contents from
+    /// the [`Source`] only when they are requested.
+    pub fn write_from_reader(
+        &self,
+        format: &str,
+        source: Source,
+    ) -> Result<(), Error> {
+        self.write_from_reader_to(Selection::Clipboard, format, source)
+    }
+
+    /// Offers the given [`Selection`] in the given format, reading the
+    /// contents from the [`Source`] only when they are requested.
+    ///
+    /// The contents are sent in chunks, so they never need to be in memory
+    /// at once.
+    pub fn write_from_reader_to(
+        &self,
+        selection: Selection,
+        format: &str,
+        source: Source,
+    ) -> Result<(), Error> {
+        let selection = self.inner.writer.atoms.selection(selection);
+        self.write_selection(
+            selection,
+            vec![(format, Data::Stream(source))],
+            x11rb::CURRENT_TIME,
+        )
+    }
+
+    /// Calls `on_change` with the [`Selection`] whose owner or contents
+    /// changed, every time it happens, for as long as it returns `true`.
+    ///
+    /// The callback runs in the thread serving the clipboard, so it should
+    /// return quickly.
+    ///
+    /// Fails with [`Error::MissingExtension`] if the X11 server does not
+    /// support the XFixes extension.
+    pub fn watch(
+        &self,
+        on_change: impl FnMut(Selection) -> bool + Send + 'static,
+    ) -> Result<(), Error> {
+        let watchers = self
+            .inner
+            .watchers
+            .as_ref()
+            .ok_or(Error::MissingExtension(xfixes::X11_EXTENSION_NAME))?;
+
+        watchers
+            .lock()
+            .map_err(|_| Error::SelectionLocked)?
+            .push(Box::new(on_change));
+
+        Ok(())
+    }
+
+    /// load value.
+    fn load(
+        &self,
+        selection: Atom,
+        target: Atom,
+        options: &ReadOptions,
+    ) -> Result<Vec<u8>, Error> {
+        let mut buff = Vec::new();
+
+        let _ = self.load_to(selection, vec![target], &mut buff, options)?;
+
+        Ok(buff)
+    }
+
+    /// Streams the selection converted to the first of the given targets the
+    /// owner supports into `output`.
+    fn load_to(
+        &self,
+        selection: Atom,
+        targets: Vec<Atom>,
+        output: &mut dyn Write,
+        options: &ReadOptions,
+    ) -> Result<usize, Error> {
+        let (sender, chunks) = mpsc::channel();
+
+        self.inner.convert(
+            selection,
+            targets,
+            options,
+            Box::new(move |chunk| sender.send(chunk).is_ok()),
+        );
+
+        let mut written = 0;
+
+        loop {
+            match self.inner.next_chunk(&chunks)? {
+                Chunk::Data(data) => {
+                    output.write_all(&data)?;
+                    written += data.len();
+                }
+                Chunk::End => return Ok(written),
+            }
+        }
+    }
+
+    /// Reads the given targets of the selection at once, with a single
+    /// `MULTIPLE` conversion.
+    ///
+    /// The targets refused by the owner are left out.
+    pub(crate) fn load_multiple(
+        &self,
+        selection: Atom,
+        targets: &[Atom],
+        options: &ReadOptions,
+    ) -> Result<Vec<(Atom, Vec<u8>)>, Error> {
+        let reader = &self.inner.reader;
+        let atoms = &reader.atoms;
+
+        let _reading = self
+            .inner
+            .reading
+            .lock()
+            .map_err(|_| Error::SelectionLocked)?;
+
+        // Every target is stored in a property of its own
+        let mut pairs = Vec::with_capacity(targets.len() * 2);
+
+        for (index, &target) in targets.iter().enumerate() {
+            pairs.push(target);
+            pairs.push(reader.intern(&format!("WINDOW_CLIPBOARD_{index}"))?);
+        }
+
+        let _ = reader.connection.change_property32(
+            xproto::PropMode::REPLACE,
+            reader.window,
+            atoms.property,
+            atoms.atom_pair,
+            &pairs,
+        )?;
+
+        self.convert_on_reader(selection, atoms.multiple, atoms.property)?;
+
+        let result = self.process_multiple(selection, options);
+
+        let cleanup = std::iter::once(atoms.property)
+            .chain(pairs.chunks_exact(2).map(|pair| pair[1]))
+            .try_for_each(|property| {
+                let _ = xproto::delete_property(
+                    &reader.connection,
+                    reader.window,
+                    property,
+                )?;
+
+                Ok::<_, Error>(())
+            })
+            .and_then(|()| Ok(reader.connection.flush()?));
+
+        let values = result?;
+        cleanup?;
+
+        Ok(values)
+    }
+
+    fn process_multiple(
+        &self,
+        selection: Atom,
+        options: &ReadOptions,
+    ) -> Result<Vec<(Atom, Vec<u8>)>, Error> {
+        let atoms = &self.inner.reader.atoms;
+        let deadline = options.deadline();
+
+        // The owner answers with the pairs it could convert, and `None` as
+        // the property of the rest
+        let mut answer = Vec::new();
+
+        let _ = self.process_event(
+            &mut answer,
+            selection,
+            atoms.multiple,
+            atoms.property,
+            deadline,
+            options,
+        )?;
+
+        let mut values = Vec::new();
+
+        for pair in parse_atoms(&answer).chunks_exact(2) {
+            let (target, property) = (pair[0], pair[1]);
+
+            if property == x11rb::NONE {
+                continue;
+            }
+
+            let mut value = Vec::new();
+
+            match self.receive(&mut value, target, property, deadline, options)
+            {
+                Ok(_) => values.push((target, value)),
+                Err(Error::UnexpectedType(_)) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// Asks the owner of the selection to store it in the given property of
+    /// the reader window, converted to the given target.
+    fn convert_on_reader(
+        &self,
+        selection: Atom,
+        target: Atom,
+        property: Atom,
+    ) -> Result<(), Error> {
+        let owner = xproto::get_selection_owner(
+            &self.inner.reader.connection,
+            selection,
+        )
+        .map_err(Into::into)
+        .and_then(|cookie| cookie.reply())?
+        .owner;
+
+        if owner == x11rb::NONE {
+            return Err(Error::EmptySelection);
+        }
+
+        let _ = xproto::convert_selection(
+            &self.inner.reader.connection,
+            self.inner.reader.window,
+            selection,
+            target,
+            property,
+            x11rb::CURRENT_TIME, // FIXME ^
+                                 // Clients should not use CurrentTime for the time argument of a ConvertSelection request.
+                                 // Instead, they should use the timestamp of the event that caused the request to be made.
+        )?;
+        self.inner.reader.connection.flush()?;
+
+        Ok(())
+    }
+
+    fn process_event(
+        &self,
+        output: &mut dyn Write,
+        selection: Atom,
+        target: Atom,
+        property: Atom,
+        deadline: Option<Instant>,
+        options: &ReadOptions,
+    ) -> Result<usize, Error> {
+        loop {
+            let event = match self.next_reader_event(deadline, options)? {
+                Some(Event::SelectionNotify(event)) => event,
+                _ => continue,
+            };
+
+            // Ignore late replies to reads that were given up on
+            if event.selection != selection
+                || event.target != target
+                || (event.property != property && event.property != x11rb::NONE)
+            {
+                continue;
+            };
+
+            // Note that setting the property argument to None indicates that the
+            // conversion requested could not be made.
+            if event.property == AtomEnum::NONE.into() {
+                return Err(Error::UnsupportedTarget(target));
+            }
+
+            return self.receive(output, target, property, deadline, options);
+        }
+    }
+
+    /// Streams the selection stored in the given property of the reader
+    /// window into `output`, following INCR transfers.
+    fn receive(
+        &self,
+        output: &mut dyn Write,
+        target: Atom,
+        property: Atom,
+        deadline: Option<Instant>,
+        options: &ReadOptions,
+    ) -> Result<usize, Error> {
+        let reader = &self.inner.reader;
+        let expected = reader.atoms.expected_type(target);
+        let mut written = 0;
+
+        let type_ = reader.read_property(
+            property,
+            expected,
+            output,
+            &mut written,
+            options.max_bytes,
+        )?;
+
+        // Reading the INCR property deleted it, which asks the owner to start
+        // sending chunks
+        if type_ != reader.atoms.incr {
+            return if type_ == expected {
+                Ok(written)
+            } else {
+                Err(Error::UnexpectedType(type_))
+            };
+        }
+
+        loop {
+            let event = match self.next_reader_event(deadline, options)? {
+                Some(Event::PropertyNotify(event)) => event,
+                _ => continue,
+            };
+
+            if event.state != xproto::Property::NEW_VALUE
+                || event.atom != property
+            {
+                continue;
+            };
+
+            let before = written;
+
+            let type_ = reader.read_property(
+                property,
+                expected,
+                output,
+                &mut written,
+                options.max_bytes,
+            )?;
+
+            if type_ != expected {
+                continue;
+            };
+
+            // An empty chunk ends the transfer
+            if written == before {
+                return Ok(written);
+            }
+        }
+    }
+
+    /// Returns the next event of the reader, if one arrives soon, serving our
+    /// own selections in the meantime.
+    ///
+    /// Fails once the deadline passes or the read is cancelled.
+    fn next_reader_event(
+        &self,
+        deadline: Option<Instant>,
+        options: &ReadOptions,
+    ) -> Result<Option<Event>, Error> {
+        // We may be reading our own selection
+        self.inner.dispatch_pending()?;
+
+        let wait = match deadline {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+
+                if left.is_zero() {
+                    return Err(Error::Timeout);
+                }
+
+                left.min(WAIT_SLICE)
+            }
+            None => WAIT_SLICE,
+        };
+
+        if options.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
+        self.inner.reader.next_event(wait)
+    }
+}
+
+pub struct Context {
+    pub connection: Connection,
+    pub screen: usize,
+    pub window: Window,
+    pub atoms: Atoms,
+}
+
+#[derive(Clone, Debug)]
+pub struct Atoms {
+    pub primary: Atom,
+    pub secondary: Atom,
+    pub clipboard: Atom,
+    pub property: Atom,
+    pub targets: Atom,
+    pub string: Atom,
+    pub utf8_string: Atom,
+    pub incr: Atom,
+    pub timestamp: Atom,
+    pub multiple: Atom,
+    pub atom_pair: Atom,
+    pub clipboard_manager: Atom,
+    pub save_targets: Atom,
+}
+
+impl Atoms {
+    /// Returns the atom naming the given [`Selection`].
+    pub fn selection(&self, selection: Selection) -> Atom {
+        match selection {
+            Selection::Clipboard => self.clipboard,
+            Selection::Primary => self.primary,
+            Selection::Secondary => self.secondary,
+        }
+    }
+
+    /// Returns the type of the property holding a conversion to the given
+    /// target.
+    fn expected_type(&self, target: Atom) -> Atom {
+        // Conversions to TARGETS and MULTIPLE are answered with atoms
+        if target == self.targets {
+            Atom::from(AtomEnum::ATOM)
+        } else if target == self.multiple {
+            self.atom_pair
+        } else {
+            target
+        }
+    }
+}
+
+/// Tells the requestor that its conversion is done, storing the contents in
+/// the given property, or failed if `None`.
+fn notify(
+    connection: &Connection,
+    request: &xproto::SelectionRequestEvent,
+    property: Option<Atom>,
+) {
+    let _ = xproto::send_event(
+        connection,
+        false,
+        request.requestor,
+        EventMask::NO_EVENT,
+        xproto::SelectionNotifyEvent {
+            response_type: xproto::SELECTION_NOTIFY_EVENT,
+            sequence: request.sequence,
+            time: request.time,
+            requestor: request.requestor,
+            selection: request.selection,
+            target: request.target,
+            property: property.unwrap_or(x11rb::NONE),
+        },
+    );
+
+    let _ = connection.flush();
+}
+
+/// Returns the atoms stored in the given property value.
+/// Returns when the given expiries and conversions need to be looked at
+/// again, if ever.
+fn next_deadline(
+    expiries: &Mutex<Vec<Expiry>>,
+    conversions: &Mutex<Option<Vec<Conversion>>>,
+) -> Option<Instant> {
+    let now = Instant::now();
+
+    let expiry = expiries
+        .lock()
+        .ok()?
+        .iter()
+        .map(|expiry| expiry.deadline)
+        .min();
+
+    let conversions = conversions.lock().ok()?;
+
+    // Cancellation can only be noticed by looking again
+    let read = conversions
+        .iter()
+        .flatten()
+        .filter_map(|conversion| match conversion.options.cancel {
+            Some(_) => Some(now + WAIT_SLICE),
+            None => conversion.deadline,
+        })
+        .min();
+
+    expiry.into_iter().chain(read).min()
+}
+
+fn parse_atoms(data: &[u8]) -> Vec<Atom> {
+    data.chunks_exact(4)
+        .map(|chunk| {
+            Atom::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])
+        })
+        .collect()
+}
+
+/// Returns the formats named by the given targets, leaving out duplicates and
+/// the targets of the selection protocol.
+fn formats(names: Vec<String>) -> Vec<String> {
+    let mut formats = Vec::with_capacity(names.len());
+
+    for name in names {
+        if !META_TARGETS.contains(&name.as_str()) && !formats.contains(&name) {
+            formats.push(name);
+        }
+    }
+
+    formats
+}
+
+/// Returns a [`Sink`] gathering the contents of a conversion, and handing
+/// them to `on_done` once complete.
+fn collect(
+    on_done: impl FnOnce(Result<Vec<u8>, Error>) + Send + 'static,
+) -> Sink {
+    let mut on_done = Some(on_done);
+    let mut contents = Vec::new();
+
+    Box::new(move |chunk| {
+        let result = match chunk {
+            Ok(Chunk::Data(data)) => {
+                contents.extend_from_slice(&data);
+                return true;
+            }
+            Ok(Chunk::End) => Ok(std::mem::take(&mut contents)),
+            Err(error) => Err(error),
+        };
+
+        if let Some(on_done) = on_done.take() {
+            on_done(result);
+        }
+
+        false
+    })
+}
+
+/// Returns the names of the targets that carry the same data as the given
+/// one, including itself first.
+fn aliases(format: &str) -> Vec<&str> {
+    if TEXT_TARGETS.contains(&format) {
+        std::iter::once(format)
+            .chain(TEXT_TARGETS.iter().copied().filter(|name| *name != format))
+            .collect()
+    } else {
+        vec![format]
+    }
+}
+
+#[inline]
+fn get_atom(connection: &Connection, name: &str) -> Result<Atom, Error> {
+    x11rb::protocol::xproto::intern_atom(connection, false, name.as_bytes())
+        .map_err(Into::into)
+        .and_then(|cookie| cookie.reply())
+        .map(|reply| reply.atom)
+        .map_err(Into::into)
+}
+
+impl Context {
+    /// Returns the next event of the connection, waiting up to the given
+    /// time for one to arrive.
+    fn next_event(&self, timeout: Duration) -> Result<Option<Event>, Error> {
+        // Events may already be queued, in which case the socket has
+        // nothing left to read
+        if let Some(event) = self.connection.poll_for_event()? {
+            return Ok(Some(event));
+        }
+
+        let stream = self.connection.stream();
+        let mut fds = [event::PollFd::new(stream, event::PollFlags::IN)];
+        let timeout = event::Timespec {
+            tv_sec: timeout.as_secs() as _,
+            tv_nsec: timeout.subsec_nanos() as _,
+        };
+
+        match event::poll(&mut fds, Some(&timeout)) {
+            Ok(_) | Err(rustix::io::Errno::INTR) => {}
+            Err(error) => return Err(io::Error::from(error).into()),
+        }
+
+        Ok(self.connection.poll_for_event()?)
+    }
+
+    pub fn new(displayname: Option<&str>) -> Result<Self, Error> {
+        let (connection, screen) = Connection::connect(displayname)?;
+        let window = connection.generate_id().map_err(|_| {
+            Error::ConnectionFailed(ConnectError::InvalidScreen)
+        })?;
+
+        {
+            let screen =
+                connection.setup().roots.get(screen).ok_or(
+                    Error::ConnectionFailed(ConnectError::InvalidScreen),
+                )?;
+
+            let _ = xproto::create_window(
                 &connection,
                 x11rb::COPY_DEPTH_FROM_PARENT,
                 window,
@@ -332,17 +1835,23 @@ impl Context {
                 ),
             )?;
 
-            let _ = connection.flush()?;
+            connection.flush()?;
         }
 
         let atoms = Atoms {
             primary: AtomEnum::PRIMARY.into(),
+            secondary: AtomEnum::SECONDARY.into(),
             clipboard: get_atom(&connection, "CLIPBOARD")?,
             property: get_atom(&connection, "THIS_CLIPBOARD_OUT")?,
             targets: get_atom(&connection, "TARGETS")?,
             string: AtomEnum::STRING.into(),
             utf8_string: get_atom(&connection, "UTF8_STRING")?,
             incr: get_atom(&connection, "INCR")?,
+            timestamp: get_atom(&connection, "WINDOW_CLIPBOARD_TIMESTAMP")?,
+            multiple: get_atom(&connection, "MULTIPLE")?,
+            atom_pair: get_atom(&connection, "ATOM_PAIR")?,
+            clipboard_manager: get_atom(&connection, "CLIPBOARD_MANAGER")?,
+            save_targets: get_atom(&connection, "SAVE_TARGETS")?,
         };
 
         Ok(Context {
@@ -352,84 +1861,758 @@ impl Context {
             atoms,
         })
     }
+
+    /// Asks the server to report changes of every [`Selection`] to our
+    /// window.
+    ///
+    /// Returns `false` if the XFixes extension is not available.
+    fn watch_selections(&self) -> Result<bool, Error> {
+        let version = self
+            .connection
+            .xfixes_query_version(5, 0)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok());
+
+        if version.is_none() {
+            return Ok(false);
+        }
+
+        for selection in [
+            self.atoms.clipboard,
+            self.atoms.primary,
+            self.atoms.secondary,
+        ] {
+            let _ = self.connection.xfixes_select_selection_input(
+                self.window,
+                selection,
+                xfixes::SelectionEventMask::SET_SELECTION_OWNER
+                    | xfixes::SelectionEventMask::SELECTION_WINDOW_DESTROY
+                    | xfixes::SelectionEventMask::SELECTION_CLIENT_CLOSE,
+            )?;
+        }
+
+        self.connection.flush()?;
+
+        Ok(true)
+    }
+
+    /// Returns the atom with the given name, creating it if necessary.
+    pub fn intern(&self, name: &str) -> Result<Atom, Error> {
+        get_atom(&self.connection, name)
+    }
+
+    /// Returns the names of the given atoms.
+    fn atom_names(&self, atoms: &[Atom]) -> Result<Vec<String>, Error> {
+        let cookies = atoms
+            .iter()
+            .map(|&atom| xproto::get_atom_name(&self.connection, atom))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut names = Vec::with_capacity(cookies.len());
+
+        for cookie in cookies {
+            names.push(
+                String::from_utf8_lossy(&cookie.reply()?.name).into_owned(),
+            );
+        }
+
+        Ok(names)
+    }
+
+    /// Streams the given property of our window into `output` in chunks,
+    /// deleting it once read.
+    ///
+    /// Returns the type of the property; nothing is written unless it is the
+    /// expected one.
+    fn read_property(
+        &self,
+        property: Atom,
+        expected: Atom,
+        output: &mut dyn Write,
+        written: &mut usize,
+        max_bytes: Option<usize>,
+    ) -> Result<Atom, Error> {
+        let mut offset = 0;
+
+        loop {
+            let reply = xproto::get_property(
+                &self.connection,
+                true,
+                self.window,
+                property,
+                Atom::from(AtomEnum::ANY),
+                offset,
+                CHUNK_LENGTH,
+            )
+            .map_err(Into::into)
+            .and_then(|cookie| cookie.reply())?;
+
+            if reply.type_ != expected {
+                return Ok(reply.type_);
+            }
+
+            let size =
+                *written + reply.value.len() + reply.bytes_after as usize;
+
+            if max_bytes.is_some_and(|max_bytes| size > max_bytes) {
+                return Err(Error::TooLarge);
+            }
+
+            output.write_all(&reply.value)?;
+            *written += reply.value.len();
+
+            if reply.bytes_after == 0 {
+                return Ok(reply.type_);
+            }
+
+            // Offsets are counted in 32-bit units, which every chunk but the
+            // last one is made of
+            offset += (reply.value.len() / 4) as u32;
+        }
+    }
 }
 
 pub struct Worker {
     context: Arc<Context>,
-    selections: Arc<RwLock<HashMap<Atom, (Atom, Vec<u8>)>>>,
+    selections: Selections,
+    watchers: Option<Watchers>,
+    expiries: Expiries,
+    conversions: Conversions,
+    /// The INCR transfers in progress, by requestor and property.
+    transfers: HashMap<(Window, Atom), Transfer>,
+    /// Where `SAVE_TARGETS` requests go, if we are the clipboard manager.
+    saves: Option<mpsc::Sender<manager::Message>>,
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let Ok(mut guard) = self.conversions.lock() else {
+            return;
+        };
+
+        // Nobody else would ever end the reads waiting for us
+        for mut conversion in guard.take().into_iter().flatten() {
+            let _ = (conversion.sink)(Err(Error::WorkerDied));
+        }
+    }
+}
+
+/// Contents sent in chunks, one every time the requestor deletes the
+/// property holding the previous one.
+struct Transfer {
+    target: Atom,
+    reader: Box<dyn Read + Send>,
+    /// Whether the final, empty chunk was sent.
+    finished: bool,
 }
 
 impl Worker {
     pub const INCR_CHUNK_SIZE: usize = 4000;
 
-    pub fn run(self) {
-        while let Ok(event) = self.context.connection.wait_for_event() {
-            match event {
-                Event::SelectionRequest(event) => {
-                    let selections = match self.selections.read().ok() {
-                        Some(selections) => selections,
-                        None => continue,
-                    };
+    pub fn run(mut self) {
+        loop {
+            // Other threads wake us up once they queued events of ours, which
+            // the connection does not report
+            let event = match next_deadline(&self.expiries, &self.conversions) {
+                Some(deadline) => self.context.next_event(
+                    deadline.saturating_duration_since(Instant::now()),
+                ),
+                None => self
+                    .context
+                    .connection
+                    .wait_for_event()
+                    .map(Some)
+                    .map_err(Error::from),
+            };
 
-                    let &(target, ref value) =
-                        match selections.get(&event.selection) {
-                            Some(key_value) => key_value,
-                            None => continue,
-                        };
-
-                    if event.target == self.context.atoms.targets {
-                        let data = [self.context.atoms.targets, target];
-
-                        self.context
-                            .connection
-                            .change_property32(
-                                xproto::PropMode::REPLACE,
-                                event.requestor,
-                                event.property,
-                                xproto::AtomEnum::ATOM,
-                                &data,
-                            )
-                            .expect("Change property");
-                    } else {
-                        let _ = self
-                            .context
-                            .connection
-                            .change_property8(
-                                xproto::PropMode::REPLACE,
-                                event.requestor,
-                                event.property,
-                                target,
-                                value,
-                            )
-                            .expect("Change property");
+            let Ok(event) = event else {
+                break;
+            };
+
+            if let Some(event) = event {
+                if let Event::DestroyNotify(event) = &event {
+                    if event.window == self.context.window {
+                        break;
                     }
+                }
+
+                self.handle(event);
+            }
+
+            self.expire();
+            self.time_out();
+        }
+    }
+
+    /// Handles every event already received, without blocking.
+    fn dispatch_pending(&mut self) -> Result<(), Error> {
+        while let Some(event) = self.context.connection.poll_for_event()? {
+            self.handle(event);
+        }
+
+        self.expire();
+        self.time_out();
+
+        Ok(())
+    }
+
+    /// Gives up the selections whose expiry passed.
+    ///
+    /// The server ignores the release of a selection set again after it was
+    /// written, and tells us with a `SelectionClear` otherwise.
+    fn expire(&mut self) {
+        let Ok(mut expiries) = self.expiries.lock() else {
+            return;
+        };
+
+        let now = Instant::now();
+        let connection = &self.context.connection;
+        let count = expiries.len();
+
+        expiries.retain(|expiry| {
+            if expiry.deadline > now {
+                return true;
+            }
+
+            let _ = xproto::set_selection_owner(
+                connection,
+                x11rb::NONE,
+                expiry.selection,
+                expiry.time,
+            );
+
+            false
+        });
+
+        if expiries.len() < count {
+            let _ = connection.flush();
+        }
+    }
+
+    /// Fails the conversions that timed out or were cancelled.
+    fn time_out(&mut self) {
+        let mut failed = Vec::new();
+
+        if let Some(conversions) = self
+            .conversions
+            .lock()
+            .as_mut()
+            .ok()
+            .and_then(|guard| guard.as_mut())
+        {
+            let now = Instant::now();
+            let mut index = 0;
+
+            while index < conversions.len() {
+                let conversion = &conversions[index];
+
+                let error = if conversion.options.is_cancelled() {
+                    Error::Cancelled
+                } else if conversion
+                    .deadline
+                    .is_some_and(|deadline| deadline <= now)
+                {
+                    Error::Timeout
+                } else {
+                    index += 1;
+                    continue;
+                };
+
+                failed.push((conversions.swap_remove(index), error));
+            }
+        }
+
+        for (conversion, error) in failed {
+            self.finish(conversion, Some(Err(error)));
+        }
+    }
+
+    /// Receives the answer of the owner to one of our conversions.
+    fn answer(&mut self, event: xproto::SelectionNotifyEvent) {
+        let Ok(mut guard) = self.conversions.lock() else {
+            return;
+        };
+
+        let Some(conversions) = guard.as_mut() else {
+            return;
+        };
+
+        // Ignore late answers to conversions that were given up on
+        let Some(index) = conversions.iter().position(|conversion| {
+            !conversion.incremental
+                && conversion.selection == event.selection
+                && conversion.targets[0] == event.target
+                && (event.property == conversion.property
+                    || event.property == x11rb::NONE)
+        }) else {
+            return;
+        };
+
+        let conversion = &mut conversions[index];
 
-                    let _ = xproto::send_event(
+        // Owners refuse unsupported targets before sending anything, so the
+        // next alias can be tried
+        let result = if event.property == x11rb::NONE {
+            let _ = conversion.targets.remove(0);
+
+            match conversion.targets.first() {
+                Some(&target) => {
+                    let requested = xproto::convert_selection(
                         &self.context.connection,
-                        false,
-                        event.requestor,
-                        EventMask::NO_EVENT,
-                        xproto::SelectionNotifyEvent {
-                            response_type: 31,
-                            sequence: event.sequence,
-                            time: event.time,
-                            requestor: event.requestor,
-                            selection: event.selection,
-                            target: event.target,
-                            property: event.property,
-                        },
-                    )
-                    .expect("Send event");
-
-                    let _ = self.context.connection.flush();
+                        self.context.window,
+                        conversion.selection,
+                        target,
+                        conversion.property,
+                        x11rb::CURRENT_TIME,
+                    );
+
+                    match requested {
+                        Ok(_) => {
+                            let _ = self.context.connection.flush();
+                            return;
+                        }
+                        Err(error) => Some(Err(error.into())),
+                    }
+                }
+                None => {
+                    Some(Err(Error::UnsupportedTarget(conversion.requested)))
+                }
+            }
+        } else {
+            let expected = self.context.atoms.expected_type(event.target);
+            let mut data = Vec::new();
+
+            match self.context.read_property(
+                conversion.property,
+                expected,
+                &mut data,
+                &mut conversion.received,
+                conversion.options.max_bytes,
+            ) {
+                // Reading the INCR property deleted it, which asks the owner
+                // to start sending chunks
+                Ok(type_) if type_ == self.context.atoms.incr => {
+                    conversion.incremental = true;
+                    return;
+                }
+                Ok(type_) if type_ == expected => {
+                    if (conversion.sink)(Ok(Chunk::Data(data))) {
+                        Some(Ok(Chunk::End))
+                    } else {
+                        None
+                    }
+                }
+                Ok(type_) => Some(Err(Error::UnexpectedType(type_))),
+                Err(error) => Some(Err(error)),
+            }
+        };
+
+        let conversion = conversions.swap_remove(index);
+        drop(guard);
+
+        self.finish(conversion, result);
+    }
+
+    /// Receives the next chunk of an INCR transfer to one of our
+    /// conversions.
+    fn receive_chunk(&mut self, property: Atom) {
+        let Ok(mut guard) = self.conversions.lock() else {
+            return;
+        };
+
+        let Some(conversions) = guard.as_mut() else {
+            return;
+        };
+
+        let Some(index) = conversions.iter().position(|conversion| {
+            conversion.incremental && conversion.property == property
+        }) else {
+            return;
+        };
+
+        let conversion = &mut conversions[index];
+        let expected = self.context.atoms.expected_type(conversion.targets[0]);
+        let mut data = Vec::new();
+
+        let result = match self.context.read_property(
+            property,
+            expected,
+            &mut data,
+            &mut conversion.received,
+            conversion.options.max_bytes,
+        ) {
+            Ok(type_) if type_ != expected => return,
+            // An empty chunk ends the transfer
+            Ok(_) if data.is_empty() => Some(Ok(Chunk::End)),
+            Ok(_) => {
+                if (conversion.sink)(Ok(Chunk::Data(data))) {
+                    return;
                 }
-                Event::SelectionClear(event) => {
-                    if let Ok(mut write_setmap) = self.selections.write() {
-                        write_setmap.remove(&event.selection);
+
+                None
+            }
+            Err(error) => Some(Err(error)),
+        };
+
+        let conversion = conversions.swap_remove(index);
+        drop(guard);
+
+        self.finish(conversion, result);
+    }
+
+    /// Ends a conversion removed from the ones in progress, handing the last
+    /// message to its sink unless it gave up.
+    fn finish(
+        &self,
+        mut conversion: Conversion,
+        last: Option<Result<Chunk, Error>>,
+    ) {
+        // Leftovers of an aborted transfer must not be mistaken for the next
+        // conversion using the property
+        let _ = xproto::delete_property(
+            &self.context.connection,
+            self.context.window,
+            conversion.property,
+        );
+        let _ = self.context.connection.flush();
+
+        if let Some(last) = last {
+            let _ = (conversion.sink)(last);
+        }
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::SelectionRequest(event) => {
+                // The clipboard manager answers once the contents are saved
+                if event.selection == self.context.atoms.clipboard_manager
+                    && event.target == self.context.atoms.save_targets
+                {
+                    if let Some(saves) = &self.saves {
+                        if saves.send(manager::Message::Save(event)).is_ok() {
+                            return;
+                        }
                     }
                 }
-                _ => (),
+
+                let property = self.respond(&event);
+
+                notify(&self.context.connection, &event, property);
+            }
+            Event::SelectionClear(event) => {
+                if let Ok(mut write_setmap) = self.selections.write() {
+                    write_setmap.remove(&event.selection);
+                }
+            }
+            Event::SelectionNotify(event) => self.answer(event),
+            Event::PropertyNotify(event)
+                if event.state == xproto::Property::DELETE =>
+            {
+                self.send_chunk(event.window, event.atom);
+            }
+            Event::PropertyNotify(event)
+                if event.state == xproto::Property::NEW_VALUE
+                    && event.window == self.context.window =>
+            {
+                self.receive_chunk(event.atom);
+            }
+            Event::DestroyNotify(event) => {
+                self.transfers
+                    .retain(|&(requestor, _), _| requestor != event.window);
+            }
+            Event::XfixesSelectionNotify(event) => {
+                let atoms = &self.context.atoms;
+
+                let selection = if event.selection == atoms.clipboard {
+                    Selection::Clipboard
+                } else if event.selection == atoms.primary {
+                    Selection::Primary
+                } else if event.selection == atoms.secondary {
+                    Selection::Secondary
+                } else {
+                    return;
+                };
+
+                if let Some(Ok(mut watchers)) =
+                    self.watchers.as_ref().map(|watchers| watchers.lock())
+                {
+                    watchers.retain_mut(|on_change| on_change(selection));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Stores the requested target in the requestor property, returning the
+    /// property on success.
+    fn respond(
+        &mut self,
+        event: &xproto::SelectionRequestEvent,
+    ) -> Option<Atom> {
+        // Obsolete clients may not provide a property, in which case the
+        // target name is used as the property name
+        let property = if event.property == x11rb::NONE {
+            event.target
+        } else {
+            event.property
+        };
+
+        if event.target == self.context.atoms.multiple {
+            self.convert_multiple(event.requestor, event.selection, property)
+        } else {
+            self.convert(
+                event.requestor,
+                event.selection,
+                event.target,
+                property,
+            )
+        }
+    }
+
+    /// Performs every conversion listed as target and property pairs in the
+    /// requestor property, like clipboard managers ask for.
+    ///
+    /// The property of every failed conversion is replaced with `None`.
+    fn convert_multiple(
+        &mut self,
+        requestor: Window,
+        selection: Atom,
+        property: Atom,
+    ) -> Option<Atom> {
+        let reply = xproto::get_property(
+            &self.context.connection,
+            false,
+            requestor,
+            property,
+            AtomEnum::ANY,
+            0,
+            u32::MAX,
+        )
+        .ok()?
+        .reply()
+        .ok()?;
+
+        let mut pairs: Vec<Atom> = reply.value32()?.collect();
+
+        for pair in pairs.chunks_exact_mut(2) {
+            if pair[1] == x11rb::NONE
+                || self
+                    .convert(requestor, selection, pair[0], pair[1])
+                    .is_none()
+            {
+                pair[1] = x11rb::NONE;
+            }
+        }
+
+        let _ = self
+            .context
+            .connection
+            .change_property32(
+                xproto::PropMode::REPLACE,
+                requestor,
+                property,
+                reply.type_,
+                &pairs,
+            )
+            .ok()?;
+
+        Some(property)
+    }
+
+    /// Stores the given target of the selection in the requestor property,
+    /// returning the property on success.
+    fn convert(
+        &mut self,
+        requestor: Window,
+        selection: Atom,
+        target: Atom,
+        property: Atom,
+    ) -> Option<Atom> {
+        let selections = self.selections.read().ok()?;
+        let targets = selections.get(&selection)?;
+
+        if target == self.context.atoms.targets {
+            let atoms = &self.context.atoms;
+
+            // Clipboard managers also take SAVE_TARGETS
+            let saving = (selection == atoms.clipboard_manager
+                && self.saves.is_some())
+            .then_some(atoms.save_targets);
+
+            let data: Vec<Atom> = [atoms.targets, atoms.multiple]
+                .iter()
+                .copied()
+                .chain(saving)
+                .chain(targets.iter().map(|&(target, _)| target))
+                .collect();
+
+            let _ = self
+                .context
+                .connection
+                .change_property32(
+                    xproto::PropMode::REPLACE,
+                    requestor,
+                    property,
+                    xproto::AtomEnum::ATOM,
+                    &data,
+                )
+                .ok()?;
+
+            return Some(property);
+        }
+
+        let (target, data) = targets
+            .iter()
+            .find(|(known, _)| *known == target)
+            .cloned()?;
+
+        // Lazy contents may take a while to produce, so we do not block
+        // writers in the meantime
+        drop(selections);
+
+        let value = match data {
+            Data::Ready(value) => value,
+            Data::Lazy { format, provider } => Arc::from(provider(&format)?),
+            Data::Stream(source) => {
+                let reader = source().ok()?;
+
+                return self
+                    .start_transfer(requestor, property, target, reader, 0);
+            }
+        };
+
+        // The property is set by a single request, so bigger values are
+        // sent in chunks
+        let limit = self
+            .context
+            .connection
+            .maximum_request_bytes()
+            .saturating_sub(REQUEST_OVERHEAD);
+
+        if value.len() > limit {
+            let size = value.len().min(u32::MAX as usize) as u32;
+            let reader = Box::new(io::Cursor::new(value));
+
+            return self
+                .start_transfer(requestor, property, target, reader, size);
+        }
+
+        let _ = self
+            .context
+            .connection
+            .change_property8(
+                xproto::PropMode::REPLACE,
+                requestor,
+                property,
+                target,
+                &value,
+            )
+            .ok()?;
+
+        Some(property)
+    }
+
+    /// Starts an INCR transfer by setting the requestor property to the lower
+    /// bound of the size of the contents.
+    fn start_transfer(
+        &mut self,
+        requestor: Window,
+        property: Atom,
+        target: Atom,
+        reader: Box<dyn Read + Send>,
+        size: u32,
+    ) -> Option<Atom> {
+        let connection = &self.context.connection;
+
+        // We need to know when the requestor deletes the property, or goes
+        // away in the middle of the transfer; our own window already tells us
+        if requestor != self.context.window {
+            let _ = xproto::change_window_attributes(
+                connection,
+                requestor,
+                &xproto::ChangeWindowAttributesAux::new().event_mask(
+                    EventMask::PROPERTY_CHANGE | EventMask::STRUCTURE_NOTIFY,
+                ),
+            )
+            .ok()?;
+        }
+
+        let _ = connection
+            .change_property32(
+                xproto::PropMode::REPLACE,
+                requestor,
+                property,
+                self.context.atoms.incr,
+                &[size],
+            )
+            .ok()?;
+
+        let _ = self.transfers.insert(
+            (requestor, property),
+            Transfer {
+                target,
+                reader,
+                finished: false,
+            },
+        );
+
+        Some(property)
+    }
+
+    /// Sends the next chunk of the transfer to the given requestor property,
+    /// if any.
+    fn send_chunk(&mut self, requestor: Window, property: Atom) {
+        let key = (requestor, property);
+
+        let Some(transfer) = self.transfers.get_mut(&key) else {
+            return;
+        };
+
+        // The requestor deleted the final chunk
+        if transfer.finished {
+            let _ = self.transfers.remove(&key);
+
+            // A MULTIPLE conversion may have started other transfers, and our
+            // own window needs its events
+            if requestor != self.context.window
+                && !self.transfers.keys().any(|&(other, _)| other == requestor)
+            {
+                let _ = xproto::change_window_attributes(
+                    &self.context.connection,
+                    requestor,
+                    &xproto::ChangeWindowAttributesAux::new()
+                        .event_mask(EventMask::NO_EVENT),
+                );
+                let _ = self.context.connection.flush();
+            }
+
+            return;
+        }
+
+        let mut chunk = vec![0; Self::INCR_CHUNK_SIZE];
+        let mut filled = 0;
+
+        // There is no way to report a failure midway, so the transfer just
+        // ends early
+        while filled < chunk.len() {
+            match transfer.reader.read(&mut chunk[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => break,
             }
         }
+
+        chunk.truncate(filled);
+        transfer.finished = chunk.is_empty();
+
+        let _ = self.context.connection.change_property8(
+            xproto::PropMode::REPLACE,
+            requestor,
+            property,
+            transfer.target,
+            &chunk,
+        );
+        let _ = self.context.connection.flush();
     }
 }
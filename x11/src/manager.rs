@@ -0,0 +1,298 @@
+use crate::{
+    notify, Clipboard, Error, ReadOptions, Selection, META_TARGETS,
+    SENSITIVE_HINTS,
+};
+
+use x11rb::connection::Connection as _;
+use x11rb::protocol::xproto::{self, AtomEnum, EventMask};
+
+use std::sync::mpsc;
+use std::thread;
+
+/// A message handled by the [`ClipboardManager`] thread.
+pub(crate) enum Message {
+    /// An owner asked to save the CLIPBOARD contents.
+    Save(xproto::SelectionRequestEvent),
+    /// The owner of the CLIPBOARD changed.
+    Changed,
+    /// Stop managing the clipboard.
+    Stop,
+}
+
+/// The CLIPBOARD contents saved from an owner.
+struct Saved {
+    owner: xproto::Window,
+    formats: Vec<(String, Vec<u8>)>,
+}
+
+/// A clipboard manager, keeping the CLIPBOARD contents of other clients
+/// available after they exit.
+///
+/// It owns the `CLIPBOARD_MANAGER` selection and accepts the `SAVE_TARGETS`
+/// requests of exiting owners, like [`Clipboard::persist`] makes, saving the
+/// targets they list. Once the owner is gone, the saved contents are offered
+/// again.
+///
+/// Contents marked as secret by their owner are never saved.
+pub struct ClipboardManager {
+    clipboard: Clipboard,
+    /// When we took the `CLIPBOARD_MANAGER` selection.
+    time: xproto::Timestamp,
+    messages: mpsc::Sender<Message>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl ClipboardManager {
+    /// Connect to the running X11 server and start managing its clipboard.
+    ///
+    /// Fails with [`Error::InvalidOwner`] if another clipboard manager is
+    /// running.
+    pub fn start() -> Result<Self, Error> {
+        let (messages, receiver) = mpsc::channel();
+        let clipboard = Clipboard::connect_with(Some(messages.clone()), true)?;
+
+        let atoms = &clipboard.inner.reader.atoms;
+        let manager = atoms.clipboard_manager;
+
+        // Round trips on the writer would queue events the worker misses
+        let owner = xproto::get_selection_owner(
+            &clipboard.inner.reader.connection,
+            manager,
+        )
+        .map_err(Into::into)
+        .and_then(|cookie| cookie.reply())?
+        .owner;
+
+        if owner != x11rb::NONE {
+            return Err(Error::InvalidOwner);
+        }
+
+        // We need to know when the owner of saved contents goes away
+        let changes = messages.clone();
+
+        clipboard.watch(move |selection| {
+            selection != Selection::Clipboard
+                || changes.send(Message::Changed).is_ok()
+        })?;
+
+        let time = clipboard.inner.timestamp()?;
+
+        clipboard.write_selection(manager, Vec::new(), time)?;
+        announce(&clipboard, time)?;
+
+        let thread = thread::Builder::new()
+            .name(String::from("clipboard_x11_manager"))
+            .spawn({
+                let clipboard = clipboard.clone();
+
+                move || run(clipboard, receiver)
+            })?;
+
+        Ok(ClipboardManager {
+            clipboard,
+            time,
+            messages,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for ClipboardManager {
+    fn drop(&mut self) {
+        let _ = self.messages.send(Message::Stop);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        let writer = &self.clipboard.inner.writer;
+
+        if let Ok(mut selections) = self.clipboard.inner.selections.write() {
+            let _ = selections.remove(&writer.atoms.clipboard_manager);
+        }
+
+        // A newer clipboard manager may have replaced us already
+        let _ = xproto::set_selection_owner(
+            &writer.connection,
+            x11rb::NONE,
+            writer.atoms.clipboard_manager,
+            self.time,
+        );
+        let _ = writer.connection.flush();
+    }
+}
+
+fn run(clipboard: Clipboard, messages: mpsc::Receiver<Message>) {
+    let mut saved: Option<Saved> = None;
+
+    for message in messages {
+        match message {
+            Message::Save(request) => {
+                saved = save(&clipboard, &request);
+
+                // The owner waits for our answer before exiting, and may not
+                // provide a property, like obsolete clients
+                let property = saved.as_ref().map(|_| {
+                    if request.property == x11rb::NONE {
+                        request.target
+                    } else {
+                        request.property
+                    }
+                });
+
+                notify(&clipboard.inner.writer.connection, &request, property);
+            }
+            Message::Changed => {
+                let Some(owner) = owner(&clipboard) else {
+                    continue;
+                };
+
+                if owner == clipboard.inner.writer.window {
+                    continue;
+                }
+
+                if owner == x11rb::NONE {
+                    if let Some(Saved { formats, .. }) = saved.take() {
+                        let formats: Vec<(&str, &[u8])> = formats
+                            .iter()
+                            .map(|(format, data)| {
+                                (format.as_str(), data.as_slice())
+                            })
+                            .collect();
+
+                        let _ = clipboard
+                            .write_formats_to(Selection::Clipboard, &formats);
+                    }
+                } else if saved
+                    .as_ref()
+                    .is_some_and(|saved| saved.owner != owner)
+                {
+                    // Somebody else copied something in the meantime
+                    saved = None;
+                }
+            }
+            Message::Stop => break,
+        }
+    }
+}
+
+/// Tells other clients that we manage the clipboard from the given time on,
+/// like ICCCM asks new owners of manager selections to.
+fn announce(
+    clipboard: &Clipboard,
+    time: xproto::Timestamp,
+) -> Result<(), Error> {
+    let writer = &clipboard.inner.writer;
+    let root = writer.connection.setup().roots[writer.screen].root;
+
+    let _ = xproto::send_event(
+        &writer.connection,
+        false,
+        root,
+        EventMask::STRUCTURE_NOTIFY,
+        xproto::ClientMessageEvent::new(
+            32,
+            root,
+            clipboard.inner.reader.intern("MANAGER")?,
+            [time, writer.atoms.clipboard_manager, writer.window, 0, 0],
+        ),
+    )?;
+    writer.connection.flush()?;
+
+    Ok(())
+}
+
+/// Reads the CLIPBOARD targets listed in the property of the request, or
+/// every target offered by the owner if there is none.
+fn save(
+    clipboard: &Clipboard,
+    request: &xproto::SelectionRequestEvent,
+) -> Option<Saved> {
+    let owner = owner(clipboard)?;
+    let atoms = &clipboard.inner.reader.atoms;
+
+    let mut targets = requested_targets(clipboard, request).unwrap_or_default();
+
+    if targets.is_empty() {
+        targets = clipboard.selection_targets(atoms.clipboard).ok()?;
+    }
+
+    let names = clipboard.inner.reader.atom_names(&targets).ok()?;
+
+    // The selection protocol targets carry no contents to save
+    let (targets, names): (Vec<_>, Vec<_>) = targets
+        .into_iter()
+        .zip(names)
+        .filter(|(_, name)| !META_TARGETS.contains(&name.as_str()))
+        .unzip();
+
+    // Owners may refuse some of their targets; we keep the rest
+    let values = clipboard
+        .load_multiple(atoms.clipboard, &targets, &ReadOptions::default())
+        .ok()?;
+
+    let mut saved = Saved {
+        owner,
+        formats: Vec::with_capacity(values.len()),
+    };
+
+    for (target, data) in values {
+        let Some(index) = targets.iter().position(|&known| known == target)
+        else {
+            continue;
+        };
+
+        let format = &names[index];
+
+        if SENSITIVE_HINTS
+            .iter()
+            .any(|&(hint, value)| format == hint && data.as_slice() == value)
+        {
+            return None;
+        }
+
+        saved.formats.push((format.clone(), data));
+    }
+
+    Some(saved)
+}
+
+/// Returns the targets listed in the property of a `SAVE_TARGETS` request.
+fn requested_targets(
+    clipboard: &Clipboard,
+    request: &xproto::SelectionRequestEvent,
+) -> Option<Vec<xproto::Atom>> {
+    if request.property == x11rb::NONE {
+        return None;
+    }
+
+    let reply = xproto::get_property(
+        &clipboard.inner.reader.connection,
+        false,
+        request.requestor,
+        request.property,
+        AtomEnum::ATOM,
+        0,
+        u32::MAX,
+    )
+    .ok()?
+    .reply()
+    .ok()?;
+
+    let targets = reply.value32()?.collect();
+
+    Some(targets)
+}
+
+/// Returns the current owner of the CLIPBOARD.
+fn owner(clipboard: &Clipboard) -> Option<xproto::Window> {
+    let reply = xproto::get_selection_owner(
+        &clipboard.inner.reader.connection,
+        clipboard.inner.reader.atoms.clipboard,
+    )
+    .ok()?
+    .reply()
+    .ok()?;
+
+    Some(reply.owner)
+}
@@ -0,0 +1,93 @@
+//! Runs a clipboard manager and its clients against a server of their own.
+//!
+//! Run with `cargo test -- --ignored` where `Xvfb` is installed.
+
+use clipboard_x11::{Clipboard, ClipboardManager, Error};
+
+use std::env;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A virtual X server, killed on drop.
+struct Xvfb(Child);
+
+impl Xvfb {
+    /// Starts a server on a free display and points `DISPLAY` at it.
+    fn start() -> Xvfb {
+        let mut child = Command::new("Xvfb")
+            .args(["-displayfd", "1", "-nolisten", "tcp"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Xvfb should be installed");
+
+        // The display number is written once the server is ready
+        let mut display = String::new();
+        let _ = BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut display)
+            .unwrap();
+
+        env::set_var("DISPLAY", format!(":{}", display.trim()));
+
+        Xvfb(child)
+    }
+}
+
+impl Drop for Xvfb {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Reads the CLIPBOARD until the given check passes, since the manager
+/// takes it over in the background.
+fn wait_for(
+    clipboard: &Clipboard,
+    check: impl Fn(Result<String, Error>) -> bool,
+) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+
+    while !check(clipboard.read()) {
+        assert!(Instant::now() < deadline, "the clipboard never settled");
+
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+#[ignore = "needs Xvfb"]
+fn keeps_contents_of_exited_owners() {
+    let _server = Xvfb::start();
+    let _manager = ClipboardManager::start().unwrap();
+
+    assert!(matches!(
+        ClipboardManager::start(),
+        Err(Error::InvalidOwner)
+    ));
+
+    let owner = Clipboard::connect().unwrap();
+    owner
+        .write_formats(&[
+            ("UTF8_STRING", b"saved"),
+            ("text/html", b"<b>saved</b>"),
+        ])
+        .unwrap();
+    owner.persist().unwrap();
+    drop(owner);
+
+    let reader = Clipboard::connect().unwrap();
+
+    wait_for(&reader, |text| text.is_ok_and(|text| text == "saved"));
+    assert_eq!(reader.read_format("text/html").unwrap(), b"<b>saved</b>");
+
+    // Secrets are refused, and go away with their owner
+    let owner = Clipboard::connect().unwrap();
+    owner.write_sensitive(String::from("secret")).unwrap();
+    assert!(owner.persist().is_err());
+    drop(owner);
+
+    wait_for(&reader, |text| matches!(text, Err(Error::EmptySelection)));
+}